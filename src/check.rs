@@ -0,0 +1,406 @@
+//! Lint a configuration file before it is archived, see `rconf check`.
+use super::configs::error::Result;
+use super::configs::include;
+use super::configs::path::{expand_path, ArchivePath, PathEntry, PathKind, Roots};
+use super::configs::ConfigArchive;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+/// A problem found in a configuration file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Problem {
+    pub file: PathBuf,
+
+    /// The line of the file the problem was found on, if it concerns a single line.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// The checked parts of a configuration file, along with their location in the file.
+#[derive(Deserialize)]
+struct Checked {
+    #[serde(default)]
+    paths: BTreeMap<String, Vec<Spanned<toml::Value>>>,
+
+    #[serde(default)]
+    include: Vec<String>,
+    manager: Option<CheckedManager>,
+}
+
+#[derive(Deserialize)]
+struct CheckedManager {
+    name: Option<Spanned<String>>,
+}
+
+/// A configured path which was already checked.
+struct Seen {
+    entry: String,
+    key: String,
+    local: PathBuf,
+    file: PathBuf,
+    line: usize,
+
+    /// The problem reported if the path does not exist, which no longer applies when an including
+    /// file makes the path optional.
+    missing: Option<Problem>,
+}
+
+struct Checker {
+    roots: Roots,
+    problems: Vec<Problem>,
+    seen: Vec<Seen>,
+
+    /// The location of the last `manager.name`, which is the name used by the configuration.
+    manager_at: Option<(PathBuf, usize)>,
+}
+
+/// Determine the kind of path of a key of the `paths` table.
+fn kind_of(key: &str) -> PathKind {
+    PathKind::ALL
+        .iter()
+        .find(|kind| kind.prefix() == Path::new(key))
+        .cloned()
+        .unwrap_or_else(|| PathKind::ROOT(key.to_string()))
+}
+
+/// Determine the line of a byte offset in the content of a file, starting at 1.
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+/// Determine whether a command can be run, either by its path or by searching `$PATH`.
+fn on_path(command: &str) -> bool {
+    if command.contains('/') {
+        return Path::new(command).is_file();
+    }
+
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+}
+
+impl Checker {
+    fn report(&mut self, file: &Path, line: Option<usize>, message: String) {
+        self.problems.push(Problem {
+            file: file.to_path_buf(),
+            line,
+            message,
+        });
+    }
+
+    /// Check the paths of a file after those of the files it includes, which are merged first.
+    fn check_file(&mut self, file: &Path) -> Result<()> {
+        let content = fs::read_to_string(file)?;
+        let checked: Checked = toml::from_str(&content)?;
+
+        let dir = fs::canonicalize(file)?
+            .parent()
+            .unwrap_or_else(|| Path::new("/"))
+            .to_path_buf();
+
+        for included in &checked.include {
+            self.check_file(&include::resolve(included, &dir)?)?;
+        }
+
+        for (key, entries) in checked.paths {
+            let kind = kind_of(&key);
+
+            for entry in entries {
+                let line = line_of(&content, entry.span().start);
+                let entry: PathEntry = entry.into_inner().try_into()?;
+
+                self.check_entry(file, line, &key, &kind, &entry);
+            }
+        }
+
+        if let Some(name) = checked.manager.and_then(|manager| manager.name) {
+            self.manager_at = Some((file.to_path_buf(), line_of(&content, name.span().start)));
+        }
+
+        // the problems of the file follow those of the files it includes, some of which may have
+        // been withdrawn by its entries
+        let start = self
+            .problems
+            .iter()
+            .rposition(|problem| problem.file != file)
+            .map_or(0, |index| index + 1);
+        self.problems[start..].sort_by_key(|problem| problem.line);
+
+        Ok(())
+    }
+
+    fn check_entry(
+        &mut self,
+        file: &Path,
+        line: usize,
+        key: &str,
+        kind: &PathKind,
+        path_entry: &PathEntry,
+    ) {
+        let entry = path_entry.path.as_str();
+        let expanded = match expand_path(entry) {
+            Ok(expanded) => expanded.into_owned(),
+            Err(err) => return self.report(file, Some(line), err.to_string()),
+        };
+
+        match kind {
+            PathKind::ABSOLUTE if !expanded.is_absolute() => {
+                return self.report(
+                    file,
+                    Some(line),
+                    format!("'{}' in paths.absolute is not an absolute path", entry),
+                )
+            }
+            PathKind::ABSOLUTE => {}
+            _ if expanded.is_absolute() => {
+                return self.report(
+                    file,
+                    Some(line),
+                    format!(
+                        "'{}' in paths.{} is an absolute path, move it to paths.absolute",
+                        entry, key
+                    ),
+                )
+            }
+            _ => {}
+        }
+
+        let path = ArchivePath {
+            kind: kind.clone(),
+            path: expanded.strip_prefix("/").unwrap_or(&expanded).into(),
+        };
+
+        let local = match path.to_local_path_in(&self.roots) {
+            Ok(local) => local,
+            Err(err) => return self.report(file, Some(line), err.to_string()),
+        };
+
+        let missing = if path_entry.optional || fs::symlink_metadata(&local).is_ok() {
+            None
+        } else {
+            Some(Problem {
+                file: file.to_path_buf(),
+                line: Some(line),
+                message: format!("'{}' does not exist at {}", entry, local.display()),
+            })
+        };
+
+        self.problems.extend(missing.clone());
+
+        let seen = Seen {
+            entry: entry.to_string(),
+            key: key.to_string(),
+            local,
+            file: file.to_path_buf(),
+            line,
+            missing,
+        };
+
+        // an including file lists a path of an included file again to replace its options
+        let overridden = self.seen.iter().position(|other| {
+            other.file != seen.file && other.key == seen.key && other.entry == seen.entry
+        });
+
+        if let Some(index) = overridden {
+            if let Some(missing) = self.seen[index].missing.take() {
+                self.problems.retain(|problem| *problem != missing);
+            }
+
+            // its overlaps with other paths were reported for the overridden entry
+            self.seen[index] = seen;
+            return;
+        }
+
+        let overlaps: Vec<String> = self
+            .seen
+            .iter()
+            .filter_map(|other| {
+                let how = if other.local == seen.local {
+                    "duplicates"
+                } else if seen.local.starts_with(&other.local) {
+                    "is inside"
+                } else if other.local.starts_with(&seen.local) {
+                    "contains"
+                } else {
+                    return None;
+                };
+
+                Some(format!(
+                    "'{}' {} '{}' at {}:{}",
+                    entry,
+                    how,
+                    other.entry,
+                    other.file.display(),
+                    other.line
+                ))
+            })
+            .collect();
+
+        for overlap in overlaps {
+            self.report(file, Some(line), overlap);
+        }
+
+        self.seen.push(seen);
+    }
+
+    /// Check that the package manager can be run, and has the arguments needed to remove the
+    /// packages, or to upgrade the system if it will be.
+    fn check_manager(&mut self, cfg: &ConfigArchive, upgrade: bool) {
+        let manager = match &cfg.manager {
+            Some(manager) => manager,
+            None => return,
+        };
+
+        let (file, line) = match &self.manager_at {
+            Some((file, line)) => (file.clone(), Some(*line)),
+            None => return,
+        };
+
+        if !on_path(&manager.name) {
+            self.report(
+                &file,
+                line,
+                format!("The package manager '{}' is not on PATH", manager.name),
+            );
+        }
+
+        if !manager.packages.is_empty() && manager.un_install_args.is_none() {
+            self.report(
+                &file,
+                line,
+                "No manager.un_install_args are given to remove the packages with".to_string(),
+            );
+        }
+
+        if upgrade && manager.upgrade_args.is_none() {
+            self.report(
+                &file,
+                line,
+                "No manager.upgrade_args are given to upgrade the system with".to_string(),
+            );
+        }
+    }
+}
+
+/// Check a configuration file and the files it includes for paths which do not exist or are
+/// given as the wrong kind, paths archived more than once, and a package manager which cannot be
+/// run or is missing arguments. When upgrading, the package manager must be able to upgrade the
+/// system.
+///
+/// # Errors
+/// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned on an error reading or
+/// parsing the files.
+pub fn check(path: &Path, upgrade: bool) -> Result<Vec<Problem>> {
+    let cfg = ConfigArchive::with_file(path)?;
+
+    let mut checker = Checker {
+        roots: cfg.roots(&BTreeMap::new())?,
+        problems: vec![],
+        seen: vec![],
+        manager_at: None,
+    };
+
+    checker.check_file(path)?;
+    checker.check_manager(&cfg, upgrade);
+
+    Ok(checker.problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, line_of, Problem};
+    use std::fs;
+
+    #[test]
+    fn test_line_of() {
+        assert_eq!(1, line_of("a\nb", 0));
+        assert_eq!(2, line_of("a\nb", 2));
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("rconf-check-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("etc/app")).unwrap();
+
+        let file = dir.join("config.toml");
+        fs::write(
+            &file,
+            format!(
+                "[paths]
+absolute = [
+    '{dir}/etc/app',
+    '{dir}/etc/app/conf',
+    'relative',
+    {{ path = '{dir}/etc/optional', optional = true }},
+]
+home = ['/etc/hosts']
+
+[manager]
+name = 'rconf-test-no-such-manager'
+install_args = ['-S']
+packages = ['git']
+",
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+
+        let problems = check(&file, true).unwrap();
+        let lines: Vec<Option<usize>> = problems.iter().map(|problem| problem.line).collect();
+
+        // the missing nested file, which is also inside the directory
+        assert_eq!(
+            vec![
+                Some(4),
+                Some(4),
+                Some(5),
+                Some(8),
+                Some(11),
+                Some(11),
+                Some(11)
+            ],
+            lines
+        );
+        assert!(problems[1].message.contains("is inside"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_override() {
+        let dir = std::env::temp_dir().join(format!("rconf-check-override-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(
+            dir.join("base.toml"),
+            format!(
+                "paths.absolute = ['{dir}/missing', '{dir}/base.toml']\n",
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+
+        let file = dir.join("work.toml");
+        fs::write(
+            &file,
+            format!(
+                "include = ['base.toml']
+paths.absolute = [
+    {{ path = '{dir}/missing', optional = true }},
+    {{ path = '{dir}/base.toml', on_conflict = 'skip' }},
+]
+",
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+
+        // repeating the paths of an included file only replaces their options
+        assert_eq!(Vec::<Problem>::new(), check(&file, false).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
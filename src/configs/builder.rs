@@ -0,0 +1,239 @@
+use super::manager::Manager;
+use super::path::{PathEntry, PathSpecifier};
+use super::ConfigArchive;
+use std::collections::BTreeMap;
+
+/// Programmatically build a [ConfigArchive](../struct.ConfigArchive.html) without writing a toml
+/// configuration file.
+#[derive(Default)]
+pub struct ConfigBuilder {
+    paths: Option<PathSpecifier>,
+    manager: Option<Manager>,
+    roots: BTreeMap<String, String>,
+}
+
+impl ConfigBuilder {
+    /// Start building a configuration without any paths, package manager, or roots.
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Retrieve the specifier to add paths to, creating it if no paths have been added yet.
+    fn specifier(&mut self) -> &mut PathSpecifier {
+        self.paths.get_or_insert_with(PathSpecifier::default)
+    }
+
+    /// Add an absolute path.
+    pub fn absolute<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().absolute.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to the user's home directory.
+    pub fn home<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().home.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to the user's config directory.
+    pub fn config<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().config.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to the user's data directory.
+    pub fn data<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().data.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to the user's state directory.
+    pub fn state<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().state.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to the user's cache directory.
+    pub fn cache<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().cache.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to the user's executable directory.
+    pub fn bin<S: Into<String>>(mut self, path: S) -> ConfigBuilder {
+        push(
+            self.specifier().bin.get_or_insert_with(Vec::new),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Add a path relative to a named root, whose directory is set by [root](#method.root).
+    pub fn root_path<N: Into<String>, S: Into<String>>(
+        mut self,
+        name: N,
+        path: S,
+    ) -> ConfigBuilder {
+        push(
+            self.specifier().roots.entry(name.into()).or_default(),
+            PathEntry::from(path.into()),
+        );
+        self
+    }
+
+    /// Set the directory of a named root, which may use environment variables like paths, see
+    /// [Roots](../path/struct.Roots.html). The name is checked when the roots are resolved.
+    pub fn root<N: Into<String>, S: Into<String>>(mut self, name: N, dir: S) -> ConfigBuilder {
+        self.roots.insert(name.into(), dir.into());
+        self
+    }
+
+    /// Add every path specified by an existing [PathSpecifier](../path/struct.PathSpecifier.html).
+    pub fn paths(mut self, paths: &PathSpecifier) -> ConfigBuilder {
+        self.specifier().extend(paths);
+        self
+    }
+
+    /// Set the package manager, see [ManagerBuilder](struct.ManagerBuilder.html).
+    pub fn manager(mut self, manager: Manager) -> ConfigBuilder {
+        self.manager = Some(manager);
+        self
+    }
+
+    /// Finish building the [ConfigArchive](../struct.ConfigArchive.html), which is not backed by
+    /// an archive.
+    pub fn build(self) -> ConfigArchive {
+        ConfigArchive {
+            roots: self.roots,
+            ..ConfigArchive::new(self.paths, self.manager)
+        }
+    }
+}
+
+/// Programmatically build a [Manager](../manager/struct.Manager.html).
+pub struct ManagerBuilder {
+    manager: Manager,
+}
+
+impl ManagerBuilder {
+    /// Start building a manager running the given command, with no arguments or packages.
+    pub fn new<S: Into<String>>(name: S) -> ManagerBuilder {
+        ManagerBuilder {
+            manager: Manager {
+                name: name.into(),
+                packages: vec![],
+                install_args: vec![],
+                un_install_args: None,
+                upgrade_args: None,
+                root_args: None,
+            },
+        }
+    }
+
+    /// Set the arguments installing the packages, which are followed by the packages.
+    pub fn install_args(mut self, args: &[&str]) -> ManagerBuilder {
+        self.manager.install_args = to_strings(args);
+        self
+    }
+
+    /// Set the arguments uninstalling the packages, which are followed by the packages.
+    pub fn un_install_args(mut self, args: &[&str]) -> ManagerBuilder {
+        self.manager.un_install_args = Some(to_strings(args));
+        self
+    }
+
+    /// Set the arguments upgrading the system before the packages are installed.
+    pub fn upgrade_args(mut self, args: &[&str]) -> ManagerBuilder {
+        self.manager.upgrade_args = Some(to_strings(args));
+        self
+    }
+
+    /// Set the arguments operating on an alternate root, see
+    /// [in_root](../manager/struct.Manager.html#method.in_root).
+    pub fn root_args(mut self, args: &[&str]) -> ManagerBuilder {
+        self.manager.root_args = Some(to_strings(args));
+        self
+    }
+
+    /// Add a package to install.
+    pub fn package<S: Into<String>>(mut self, package: S) -> ManagerBuilder {
+        push(&mut self.manager.packages, package.into());
+        self
+    }
+
+    /// Finish building the [Manager](../manager/struct.Manager.html).
+    pub fn build(self) -> Manager {
+        self.manager
+    }
+}
+
+/// Add a value to a list unless it is already present.
+fn push<T: PartialEq>(list: &mut Vec<T>, value: T) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+/// Copy borrowed arguments into owned strings.
+fn to_strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigBuilder, ManagerBuilder};
+
+    #[test]
+    fn test_build() {
+        let cfg = ConfigBuilder::new()
+            .home(".bashrc")
+            .home(".bashrc")
+            .absolute("/etc/gitconfig")
+            .data("fonts")
+            .root("workspace", "$WORKSPACE")
+            .root_path("workspace", ".editorconfig")
+            .manager(
+                ManagerBuilder::new("pacman")
+                    .install_args(&["-S"])
+                    .package("fish")
+                    .build(),
+            )
+            .build();
+
+        assert_eq!(Some(&"$WORKSPACE".to_string()), cfg.roots.get("workspace"));
+
+        let paths = cfg.paths.unwrap();
+        assert_eq!(Some(vec![".bashrc".into()]), paths.home);
+        assert_eq!(Some(vec!["/etc/gitconfig".into()]), paths.absolute);
+        assert!(paths.config.is_none());
+        assert_eq!(Some(vec!["fonts".into()]), paths.data);
+        assert_eq!(
+            Some(&vec![".editorconfig".into()]),
+            paths.roots.get("workspace")
+        );
+
+        let manager = cfg.manager.unwrap();
+        assert_eq!("pacman", manager.name);
+        assert_eq!(vec!["-S".to_string()], manager.install_args);
+        assert_eq!(vec!["fish".to_string()], manager.packages);
+        assert!(manager.un_install_args.is_none());
+    }
+}
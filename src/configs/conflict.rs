@@ -0,0 +1,488 @@
+use super::error::Result;
+use super::path::PathSpecifier;
+use super::state::InstallState;
+use diffy::DiffOptions;
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tar::Entry;
+
+/// The suffix appended to the name of a local file moved aside by
+/// [ConflictPolicy::Backup](enum.ConflictPolicy.html#variant.Backup).
+pub const BACKUP_SUFFIX: &str = ".rconf.bak";
+
+/// What to do when an archived file is installed over a local file with different content.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Replace the local file.
+    #[default]
+    Overwrite,
+
+    /// Keep the local file.
+    Skip,
+
+    /// Move the local file aside before replacing it.
+    Backup,
+
+    /// Replace the local file only if the archived file was modified more recently.
+    Newer,
+
+    /// Show the differences and ask before replacing the local file.
+    Prompt,
+}
+
+impl ConflictPolicy {
+    /// The names of every policy as accepted on the command line and in the configuration.
+    pub const NAMES: &'static [&'static str] = &["overwrite", "skip", "backup", "newer", "prompt"];
+
+    /// Parse a policy from its name.
+    pub fn from_name(name: &str) -> Option<ConflictPolicy> {
+        match name {
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "skip" => Some(ConflictPolicy::Skip),
+            "backup" => Some(ConflictPolicy::Backup),
+            "newer" => Some(ConflictPolicy::Newer),
+            "prompt" => Some(ConflictPolicy::Prompt),
+            _ => None,
+        }
+    }
+}
+
+/// What was done when installing an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// The archived entry was written to its destination.
+    Written,
+
+    /// The local file was kept.
+    Skipped,
+
+    /// The local file was moved to the given path before the archived entry was written.
+    BackedUp(PathBuf),
+
+    /// The changes to the local file and the archived file since the last install were merged,
+    /// with the given lines of the local file starting a conflict between them.
+    Merged(Vec<usize>),
+}
+
+/// The conflict policy of every entry of an archive. Entries use the policy of the most specific
+/// configured path containing them which specifies one, or the default policy otherwise.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConflictPolicies {
+    pub default: ConflictPolicy,
+
+    /// The archive paths of the configured paths specifying a policy.
+    overrides: Vec<(PathBuf, ConflictPolicy)>,
+}
+
+impl ConflictPolicies {
+    /// Collect the policies of the configured paths.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if a configured path uses
+    /// an unset variable.
+    pub fn new(default: ConflictPolicy, paths: Option<&PathSpecifier>) -> Result<ConflictPolicies> {
+        let overrides = match paths {
+            Some(paths) => paths
+                .path_entries()?
+                .into_iter()
+                .filter_map(|(path, entry)| Some((path.to_tar_path(), entry.on_conflict?)))
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(ConflictPolicies { default, overrides })
+    }
+
+    /// Whether any entry may use the given policy.
+    pub fn uses(&self, policy: ConflictPolicy) -> bool {
+        self.default == policy || self.overrides.iter().any(|(_, other)| *other == policy)
+    }
+
+    /// Retrieve the policy of the entry with the given archive path.
+    pub fn for_path(&self, tar_path: &Path) -> ConflictPolicy {
+        self.overrides
+            .iter()
+            .filter(|(path, _)| tar_path.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
+
+/// Build the path of a hidden file beside the given path.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+
+    path.with_file_name(name)
+}
+
+/// Read the content of a local file, or none if only a privileged user may read it, such as a
+/// system file replaced by an [Escalation](../escalate/struct.Escalation.html).
+fn read_local(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Determine whether an archived file and a local path are files or symbolic links with the same
+/// content. A local file which cannot be read is assumed to differ.
+fn same_content(archived: &Path, local: &Path) -> Result<bool> {
+    let (a_meta, b_meta) = (
+        fs::symlink_metadata(archived)?,
+        fs::symlink_metadata(local)?,
+    );
+
+    if a_meta.file_type().is_symlink() && b_meta.file_type().is_symlink() {
+        Ok(fs::read_link(archived)? == fs::read_link(local)?)
+    } else if a_meta.is_file() && b_meta.is_file() && a_meta.len() == b_meta.len() {
+        Ok(read_local(local)? == Some(fs::read(archived)?))
+    } else {
+        Ok(false)
+    }
+}
+
+/// Show the differences between a local file and its archived replacement, and ask whether to
+/// replace it.
+fn prompt(dst: &Path, archived: &Path) -> Result<bool> {
+    let new = fs::read(archived).unwrap_or_default();
+
+    // the local file may only be readable by a privileged user
+    match fs::read(dst) {
+        Ok(local) => match (std::str::from_utf8(&local), std::str::from_utf8(&new)) {
+            (Ok(local), Ok(new)) => eprint!(
+                "{}",
+                DiffOptions::new()
+                    .set_original_filename(dst.to_string_lossy().into_owned())
+                    .set_modified_filename("archived")
+                    .create_patch(local, new)
+            ),
+            _ => eprintln!(
+                "Binary file {} differs from the archived file",
+                dst.display()
+            ),
+        },
+        Err(_) => eprintln!(
+            "{} cannot be read to compare it with the archived file",
+            dst.display()
+        ),
+    }
+
+    eprint!("Overwrite {}? [y/N] ", dst.display());
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Merge the changes made to a local file and to its archived replacement since the content
+/// recorded when it was last installed, or none if there is no such content, any is not text, or
+/// the local file cannot be read.
+/// Conflicting changes are both kept between conflict markers, and the lines starting each conflict
+/// are returned along with the merged content.
+fn merge_with_base(
+    state: &InstallState,
+    dst: &Path,
+    archived: &Path,
+) -> Result<Option<(String, Vec<usize>)>> {
+    let base = match state.base(dst)? {
+        Some(base) => base,
+        None => return Ok(None),
+    };
+    let local = match read_local(dst)? {
+        Some(local) => local,
+        None => return Ok(None),
+    };
+    let new = fs::read(archived)?;
+
+    let (base, local, new) = match (
+        std::str::from_utf8(&base),
+        std::str::from_utf8(&local),
+        std::str::from_utf8(&new),
+    ) {
+        (Ok(base), Ok(local), Ok(new)) => (base, local, new),
+        _ => return Ok(None),
+    };
+
+    match diffy::merge(base, local, new) {
+        Ok(merged) => Ok(Some((merged, vec![]))),
+        Err(conflicted) => {
+            let conflicts = conflicted
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.starts_with("<<<<<<<"))
+                .map(|(index, _)| index + 1)
+                .collect();
+
+            Ok(Some((conflicted, conflicts)))
+        }
+    }
+}
+
+/// Record the content installed to a local file, if it is a regular file.
+fn record(state: Option<&InstallState>, dst: &Path, content: &Path) -> Result<()> {
+    match state {
+        Some(state) if fs::symlink_metadata(content)?.is_file() => state.record(dst, content),
+        _ => Ok(()),
+    }
+}
+
+/// Determine whether the archived entry was modified more recently than the local file.
+fn is_newer<R: Read>(entry: &Entry<R>, local: &Metadata) -> Result<bool> {
+    let local = local
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+
+    Ok(entry.header().mtime()? > local)
+}
+
+/// Unpack an archived entry to its destination, applying the policy if the destination is an
+/// existing file with different content. Directories are unpacked into existing directories.
+///
+/// The content of every installed file is recorded in the state, if any. When merging, the
+/// changes to a local file since it was recorded are merged with the archived file instead, and
+/// the policy only applies to files which cannot be merged.
+///
+/// The entry is first unpacked beside its destination so that it can be compared to the local
+/// file, and then renamed over it. When a staged path is given the entry is unpacked there instead
+/// and the destination is left untouched, nothing is staged if the local file is kept or already
+/// has the archived content, see [Escalation](../escalate/struct.Escalation.html).
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error unpacking the
+/// entry, reading the local file, asking whether to replace it, or recording its content.
+pub fn unpack_with_policy<R: Read>(
+    policy: ConflictPolicy,
+    state: Option<&InstallState>,
+    merge: bool,
+    entry: &mut Entry<R>,
+    dst: &Path,
+    staged: Option<&Path>,
+) -> Result<Outcome> {
+    let out = staged.unwrap_or(dst);
+
+    let existing = match fs::symlink_metadata(dst) {
+        Ok(existing) if !existing.is_dir() && !entry.header().entry_type().is_dir() => existing,
+        _ => {
+            entry.unpack(out)?;
+            record(state, dst, out)?;
+
+            return Ok(Outcome::Written);
+        }
+    };
+
+    let tmp = sibling(out, ".rconf-tmp");
+    entry.unpack(&tmp)?;
+
+    let resolve = || -> Result<Outcome> {
+        if same_content(&tmp, dst)? {
+            record(state, dst, &tmp)?;
+
+            // there is nothing to copy to the destination later
+            if staged.is_some() {
+                fs::remove_file(&tmp)?;
+            }
+
+            return Ok(Outcome::Written);
+        }
+
+        if let (true, Some(state)) = (merge, state) {
+            if let Some((merged, conflicts)) = merge_with_base(state, dst, &tmp)? {
+                // the archived content is the base of the next merge rather than the merged content
+                state.record(dst, &tmp)?;
+
+                // the archived file is merged in place so that it keeps its permissions
+                fs::write(&tmp, merged)?;
+
+                return Ok(Outcome::Merged(conflicts));
+            }
+        }
+
+        let replace = match policy {
+            ConflictPolicy::Overwrite | ConflictPolicy::Backup => true,
+            ConflictPolicy::Skip => false,
+            ConflictPolicy::Newer => is_newer(entry, &existing)?,
+            ConflictPolicy::Prompt => prompt(dst, &tmp)?,
+        };
+
+        if !replace {
+            return Ok(Outcome::Skipped);
+        }
+
+        record(state, dst, &tmp)?;
+
+        if policy == ConflictPolicy::Backup {
+            let mut backup = dst.as_os_str().to_owned();
+            backup.push(BACKUP_SUFFIX);
+            let backup = PathBuf::from(backup);
+
+            if staged.is_none() {
+                fs::rename(dst, &backup)?;
+            }
+
+            return Ok(Outcome::BackedUp(backup));
+        }
+
+        Ok(Outcome::Written)
+    };
+
+    match resolve() {
+        Ok(Outcome::Skipped) => {
+            fs::remove_file(&tmp)?;
+            Ok(Outcome::Skipped)
+        }
+        Ok(outcome) => {
+            if fs::symlink_metadata(&tmp).is_ok() {
+                fs::rename(&tmp, out)?;
+            }
+
+            Ok(outcome)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&tmp);
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unpack_with_policy, ConflictPolicies, ConflictPolicy, Outcome, BACKUP_SUFFIX};
+    use crate::configs::path::PathSpecifier;
+    use crate::configs::state::InstallState;
+    use std::fs;
+    use std::path::Path;
+    use tar::{Archive, Builder, Header};
+
+    /// Build an archive containing a single file with the given content and modification time.
+    fn archive(content: &str, mtime: u64) -> Vec<u8> {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+
+        let mut builder = Builder::new(vec![]);
+        builder
+            .append_data(&mut header, "file", content.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn install(policy: ConflictPolicy, content: &str, mtime: u64, dst: &Path) -> Outcome {
+        install_with_state(policy, None, content, mtime, dst)
+    }
+
+    /// Install the content, merging it with the local file if there is a state.
+    fn install_with_state(
+        policy: ConflictPolicy,
+        state: Option<&InstallState>,
+        content: &str,
+        mtime: u64,
+        dst: &Path,
+    ) -> Outcome {
+        let data = archive(content, mtime);
+        let mut archive = Archive::new(data.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+
+        unpack_with_policy(policy, state, state.is_some(), &mut entry, dst, None).unwrap()
+    }
+
+    #[test]
+    fn test_policies() {
+        let dir = std::env::temp_dir().join(format!("rconf-conflict-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dst = dir.join("file");
+
+        fs::write(&dst, "local").unwrap();
+        assert_eq!(
+            Outcome::Skipped,
+            install(ConflictPolicy::Skip, "archived", 0, &dst)
+        );
+        assert_eq!("local", fs::read_to_string(&dst).unwrap());
+
+        assert_eq!(
+            Outcome::Skipped,
+            install(ConflictPolicy::Newer, "archived", 0, &dst)
+        );
+        assert_eq!(
+            Outcome::Written,
+            install(ConflictPolicy::Newer, "archived", u32::MAX as u64, &dst)
+        );
+        assert_eq!("archived", fs::read_to_string(&dst).unwrap());
+
+        let backup = dir.join(format!("file{}", BACKUP_SUFFIX));
+        assert_eq!(
+            Outcome::BackedUp(backup.clone()),
+            install(ConflictPolicy::Backup, "new", 0, &dst)
+        );
+        assert_eq!("archived", fs::read_to_string(&backup).unwrap());
+        assert_eq!("new", fs::read_to_string(&dst).unwrap());
+
+        // identical content is never a conflict
+        assert_eq!(
+            Outcome::Written,
+            install(ConflictPolicy::Prompt, "new", 0, &dst)
+        );
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = std::env::temp_dir().join(format!("rconf-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dst = dir.join("file");
+        let state = InstallState::with_dir(dir.join("state"));
+
+        let install =
+            |content| install_with_state(ConflictPolicy::Skip, Some(&state), content, 0, &dst);
+
+        assert_eq!(Outcome::Written, install("a\nb\nc\n"));
+
+        fs::write(&dst, "a\nB\nc\n").unwrap();
+        assert_eq!(Outcome::Merged(vec![]), install("a\nb\nc\nd\n"));
+        assert_eq!("a\nB\nc\nd\n", fs::read_to_string(&dst).unwrap());
+
+        fs::write(&dst, "X\nB\nc\nd\n").unwrap();
+        assert_eq!(Outcome::Merged(vec![1]), install("Y\nb\nc\nd\n"));
+        assert!(fs::read_to_string(&dst).unwrap().starts_with("<<<<<<<"));
+
+        // files without a recorded base fall back to the policy
+        fs::remove_dir_all(&state.dir).unwrap();
+        assert_eq!(Outcome::Skipped, install("Z\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy_for_path() {
+        let paths: PathSpecifier = toml::from_str(
+            "home = ['.bashrc', { path = '.config', on_conflict = 'skip' }, { path = '.config/nvim', on_conflict = 'backup' }]",
+        )
+        .unwrap();
+        let policies = ConflictPolicies::new(ConflictPolicy::Newer, Some(&paths)).unwrap();
+
+        assert_eq!(
+            ConflictPolicy::Newer,
+            policies.for_path(Path::new("home/.bashrc"))
+        );
+        assert_eq!(
+            ConflictPolicy::Skip,
+            policies.for_path(Path::new("home/.config/fish"))
+        );
+        assert_eq!(
+            ConflictPolicy::Backup,
+            policies.for_path(Path::new("home/.config/nvim/init.vim"))
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as fmtResult};
 use std::io::Error as ioError;
+use std::path::PathBuf;
 use toml::de::Error as deError;
 
 pub type Result<T> = std::result::Result<T, ConfigError>;
@@ -12,7 +14,80 @@ pub enum ConfigError {
     Deserialize(deError),
     DirNotFound(String),
     FieldNotFound(String),
-    Manager(String, Vec<String>)
+
+    /// A configured path uses an environment variable which is not set.
+    UnsetVariable {
+        variable: String,
+        entry: String,
+    },
+
+    /// A path uses a root which has no directory.
+    UnknownRoot(String),
+
+    /// A root whose name is that of a built in kind of path, or which is not a single directory
+    /// name.
+    InvalidRoot(String),
+
+    /// A configured path which is neither a file nor a directory, when archiving strictly.
+    MissingPath {
+        entry: String,
+        local: PathBuf,
+    },
+
+    /// The destination of an archived path which is not absolute, such as beneath a relative root
+    /// directory, where an absolute destination is required.
+    RelativeDestination {
+        path: PathBuf,
+        destination: PathBuf,
+    },
+
+    /// A local path beneath a configured path which could not be archived, when archiving
+    /// strictly.
+    SkippedPath {
+        local: PathBuf,
+        reason: String,
+    },
+
+    /// A user which is not in the passwd database.
+    UnknownUser(String),
+
+    /// An archive whose manifest has a newer format than this version of rconf can read, along
+    /// with the version of rconf which wrote it if known.
+    UnsupportedFormat {
+        version: u64,
+        written_by: Option<String>,
+    },
+
+    /// An archive whose manifest has a format version which is not a non-negative integer, as it
+    /// was written in the manifest.
+    InvalidFormat(String),
+
+    /// An archive whose first entry is not the manifest, such as a tar not written by rconf.
+    MissingManifest,
+
+    /// Configuration files which include each other, ending with the first file included again.
+    IncludeCycle(Vec<PathBuf>),
+
+    /// Problems were found by checking a configuration file.
+    Problems(usize),
+
+    /// A command line argument or option which could not be used.
+    Argument(String),
+
+    /// A command could not be run.
+    Spawn {
+        command: String,
+        args: Vec<String>,
+        source: ioError,
+    },
+
+    /// A command exited unsuccessfully, with the last lines it wrote to stderr.
+    Exit {
+        command: String,
+        args: Vec<String>,
+        code: Option<i32>,
+        stderr: String,
+    },
 }
 
 impl Display for ConfigError {
@@ -28,16 +103,178 @@ impl Display for ConfigError {
                 "No value spefied for '{}' which is required by this operation",
                 s
             ),
-            ConfigError::Manager(cmd, args) => write!(f, "An error ocurred running '{} {}'", cmd, args.join(" "))
+            ConfigError::UnsetVariable { variable, entry } => write!(
+                f,
+                "The variable '{}' used by the path '{}' is not set",
+                variable, entry
+            ),
+            ConfigError::UnknownRoot(name) => write!(
+                f,
+                "No directory is defined for the root '{}', add it to the roots table or give it with --root {}=DIR",
+                name, name
+            ),
+            ConfigError::InvalidRoot(name) => write!(
+                f,
+                "The root name '{}' cannot be used, it must be a single directory name other than those of the built in kinds of paths",
+                name
+            ),
+            ConfigError::MissingPath { entry, local } => write!(
+                f,
+                "The path '{}' does not exist at {}, mark it with optional = true if it may be missing",
+                entry,
+                local.display()
+            ),
+            ConfigError::RelativeDestination { path, destination } => write!(
+                f,
+                "The archived path '{}' has the relative destination '{}', but an absolute destination is required",
+                path.display(),
+                destination.display()
+            ),
+            ConfigError::SkippedPath { local, reason } => {
+                write!(f, "Could not archive {} which {}", local.display(), reason)
+            }
+            ConfigError::UnknownUser(name) => write!(f, "There is no user named '{}'", name),
+            ConfigError::UnsupportedFormat { version, written_by } => {
+                write!(
+                    f,
+                    "The archive's manifest has format version {}, but this version of rconf only reads up to version {}",
+                    version,
+                    crate::configs::migrate::FORMAT_VERSION
+                )?;
+
+                match written_by {
+                    Some(written_by) => write!(f, ", upgrade rconf to at least {} to read it", written_by),
+                    None => write!(f, ", upgrade rconf to read it"),
+                }
+            }
+            ConfigError::InvalidFormat(version) => write!(
+                f,
+                "The archive's manifest has the invalid format version {}, which must be a non-negative integer",
+                version
+            ),
+            ConfigError::MissingManifest => write!(
+                f,
+                "The archive does not start with a manifest, install it with --plain to treat it as a plain tar"
+            ),
+            ConfigError::IncludeCycle(files) => {
+                let files: Vec<_> = files.iter().map(|file| file.display().to_string()).collect();
+                write!(f, "Configuration files include each other: {}", files.join(" -> "))
+            }
+            ConfigError::Problems(count) => write!(f, "Found {} problem(s) in the configuration", count),
+            ConfigError::Argument(s) => write!(f, "{}", s),
+            ConfigError::Spawn { command, args, source } => write!(f, "Could not run '{} {}': {}", command, args.join(" "), source),
+            ConfigError::Exit { command, args, code, stderr } => {
+                match code {
+                    Some(code) => write!(f, "'{} {}' exited with code {}", command, args.join(" "), code)?,
+                    None => write!(f, "'{} {}' was terminated by a signal", command, args.join(" "))?,
+                }
+
+                if stderr.is_empty() {
+                    Ok(())
+                } else {
+                    write!(f, ":\n{}", stderr)
+                }
+            }
         }
     }
 }
 
+impl ConfigError {
+    /// A short identifier for the kind of error which will not change between releases, for use
+    /// by programs consuming rconf's machine readable output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConfigError::Io(_) => "io",
+            ConfigError::Deserialize(_) => "parse",
+            ConfigError::DirNotFound(_) => "dir_not_found",
+            ConfigError::FieldNotFound(_) => "field_not_found",
+            ConfigError::UnsetVariable { .. } => "unset_variable",
+            ConfigError::UnknownRoot(_) => "unknown_root",
+            ConfigError::InvalidRoot(_) => "invalid_root",
+            ConfigError::MissingPath { .. } => "missing_path",
+            ConfigError::RelativeDestination { .. } => "relative_destination",
+            ConfigError::SkippedPath { .. } => "skipped_path",
+            ConfigError::UnknownUser(_) => "unknown_user",
+            ConfigError::UnsupportedFormat { .. } => "unsupported_format",
+            ConfigError::InvalidFormat(_) => "invalid_format",
+            ConfigError::MissingManifest => "missing_manifest",
+            ConfigError::IncludeCycle(_) => "include_cycle",
+            ConfigError::Problems(_) => "check_failed",
+            ConfigError::Argument(_) => "invalid_argument",
+            ConfigError::Spawn { .. } => "spawn_failed",
+            ConfigError::Exit { .. } => "exit_status",
+        }
+    }
+}
+
+/// Errors are serialized as an object with their [code](enum.ConfigError.html#method.code), a
+/// human readable message, and the details of any command which failed.
+impl Serialize for ConfigError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+
+        match self {
+            ConfigError::DirNotFound(name) => map.serialize_entry("dir", name)?,
+            ConfigError::FieldNotFound(name) => map.serialize_entry("field", name)?,
+            ConfigError::UnsetVariable { variable, entry } => {
+                map.serialize_entry("variable", variable)?;
+                map.serialize_entry("path", entry)?;
+            }
+            ConfigError::UnknownRoot(name) | ConfigError::InvalidRoot(name) => {
+                map.serialize_entry("root", name)?
+            }
+            ConfigError::MissingPath { entry, local } => {
+                map.serialize_entry("path", entry)?;
+                map.serialize_entry("local", local)?;
+            }
+            ConfigError::RelativeDestination { path, destination } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("destination", destination)?;
+            }
+            ConfigError::SkippedPath { local, reason } => {
+                map.serialize_entry("local", local)?;
+                map.serialize_entry("reason", reason)?;
+            }
+            ConfigError::UnknownUser(name) => map.serialize_entry("user", name)?,
+            ConfigError::UnsupportedFormat {
+                version,
+                written_by,
+            } => {
+                map.serialize_entry("format_version", version)?;
+                map.serialize_entry("written_by", written_by)?;
+            }
+            ConfigError::InvalidFormat(version) => map.serialize_entry("format_version", version)?,
+            ConfigError::IncludeCycle(files) => map.serialize_entry("files", files)?,
+            ConfigError::Spawn { command, args, .. } => {
+                map.serialize_entry("command", command)?;
+                map.serialize_entry("args", args)?;
+            }
+            ConfigError::Exit {
+                command,
+                args,
+                code,
+                stderr,
+            } => {
+                map.serialize_entry("command", command)?;
+                map.serialize_entry("args", args)?;
+                map.serialize_entry("exit_code", code)?;
+                map.serialize_entry("stderr", stderr)?;
+            }
+            _ => {}
+        }
+
+        map.end()
+    }
+}
+
 impl Error for ConfigError {
     fn cause(&self) -> Option<&dyn Error> {
         match self {
             ConfigError::Io(ref err) => Some(err),
             ConfigError::Deserialize(ref err) => Some(err),
+            ConfigError::Spawn { source, .. } => Some(source),
             _ => None,
         }
     }
@@ -0,0 +1,276 @@
+use super::conflict::Outcome;
+use super::error::{ConfigError, Result};
+use super::manager::{self, Invocation, Manager};
+use super::path::{ArchivePath, PathKind};
+use crate::script::quote;
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// The privileged steps of an install, which are run together by a single command such as `sudo`
+/// or `doas` so that the user is only asked for their password once.
+///
+/// Archived files needing privileges are written to a staging directory, and copied to their
+/// destination by the escalated command once every entry was installed.
+pub struct Escalation {
+    /// The command and arguments running a command with elevated privileges (ex `["sudo"]`).
+    pub command: Vec<String>,
+
+    /// The user's home directory, nothing beneath which needs privileges.
+    pub home: PathBuf,
+
+    staging: PathBuf,
+    steps: Vec<Vec<String>>,
+}
+
+impl Escalation {
+    /// Create an escalation with a new empty staging directory, see
+    /// [create_staging](fn.create_staging.html).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if the command is empty,
+    /// or on an error creating the staging directory.
+    pub fn new(command: Vec<String>, home: PathBuf) -> Result<Escalation> {
+        if command.is_empty() {
+            return Err(ConfigError::Argument(
+                "No command was given to escalate privileges with".to_string(),
+            ));
+        }
+
+        Ok(Escalation {
+            command,
+            home,
+            staging: create_staging()?,
+            steps: vec![],
+        })
+    }
+
+    /// Determine whether writing an archived entry to its local path needs privileges, which is
+    /// the case for absolute paths outside of the user's home directory.
+    pub fn covers(&self, path: &ArchivePath, dst: &Path) -> bool {
+        path.kind == PathKind::ABSOLUTE && !dst.starts_with(&self.home)
+    }
+
+    /// Retrieve the location in the staging directory a local path is written to before it is
+    /// copied to the path.
+    pub fn staged(&self, dst: &Path) -> PathBuf {
+        self.staging.join(dst.strip_prefix("/").unwrap_or(dst))
+    }
+
+    /// Add a step running a command.
+    pub fn push(&mut self, command: &str, args: &[String]) {
+        self.steps.push(
+            std::iter::once(command.to_string())
+                .chain(args.iter().cloned())
+                .collect(),
+        );
+    }
+
+    /// Add the steps replacing a local path with its staged content, if any was staged, moving a
+    /// backed up local file aside first.
+    pub fn replace(&mut self, dst: &Path, outcome: &Outcome) {
+        let staged = self.staged(dst);
+        let word = |path: &Path| path.to_string_lossy().to_string();
+
+        let staged_meta = match fs::symlink_metadata(&staged) {
+            Ok(staged_meta) => staged_meta,
+            Err(_) => return,
+        };
+
+        if let Outcome::BackedUp(backup) = outcome {
+            self.push("mv", &[word(dst), word(backup)]);
+        }
+
+        if staged_meta.is_dir() {
+            self.push("mkdir", &["-p".to_string(), word(dst)]);
+            return;
+        }
+
+        if let Some(parent) = dst.parent() {
+            self.push("mkdir", &["-p".to_string(), word(parent)]);
+        }
+
+        self.push("cp", &["-P".to_string(), word(&staged), word(dst)]);
+    }
+
+    /// Add the step removing a local file or directory.
+    pub fn remove(&mut self, dst: &Path) {
+        self.push(
+            "rm",
+            &["-rf".to_string(), dst.to_string_lossy().to_string()],
+        );
+    }
+
+    /// Run every step in order with a single escalated shell, or nothing if there are no steps.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if the escalated shell
+    /// could not be run or exited unsuccessfully.
+    pub fn run(&mut self) -> Result<Option<Invocation>> {
+        if self.steps.is_empty() {
+            return Ok(None);
+        }
+
+        let lines: Vec<String> = self
+            .steps
+            .drain(..)
+            .map(|step| {
+                step.iter()
+                    .map(|word| quote(word))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+        let script = format!("set -e\n{}\n", lines.join("\n"));
+
+        let args: Vec<String> = self.command[1..]
+            .iter()
+            .cloned()
+            .chain(vec!["sh".to_string(), "-c".to_string(), script])
+            .collect();
+
+        manager::run(&self.command[0], args).map(Some)
+    }
+}
+
+impl Drop for Escalation {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.staging);
+    }
+}
+
+/// Create a staging directory in the temporary directory which only the current user can access.
+///
+/// The directory is given a random name by `mkdtemp`, which creates it with mode 0700 and never
+/// reuses an existing directory, so another user can not prepare the directory the escalated
+/// command copies from.
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error creating the
+/// directory, or if it is not a directory owned by and only accessible to the current user.
+fn create_staging() -> Result<PathBuf> {
+    let template = std::env::temp_dir().join("rconf-escalate-XXXXXX");
+    let mut template = CString::new(template.as_os_str().as_bytes())
+        .map_err(io::Error::from)?
+        .into_bytes_with_nul();
+
+    if unsafe { libc::mkdtemp(template.as_mut_ptr() as *mut libc::c_char) }.is_null() {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    template.pop();
+    let staging = PathBuf::from(OsString::from_vec(template));
+
+    // the escalated command trusts whatever is staged, so nothing is staged into a directory
+    // which anyone else could have written to
+    let meta = fs::symlink_metadata(&staging)?;
+
+    if !meta.is_dir() || meta.uid() != unsafe { libc::geteuid() } || meta.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "The staging directory '{}' is not private to the current user",
+                staging.display()
+            ),
+        )
+        .into());
+    }
+
+    Ok(staging)
+}
+
+/// Run a package manager command, or add it to the escalated steps if privileges are escalated.
+/// Without stdin the package manager can not read from it, such as when it is the archive.
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned if the package manager could
+/// not be run or exited unsuccessfully.
+pub(crate) fn run_manager(
+    manager: &Manager,
+    args: Vec<String>,
+    escalation: Option<&mut Escalation>,
+    no_stdin: bool,
+) -> Result<Option<Invocation>> {
+    let stdin = if no_stdin {
+        Stdio::null()
+    } else {
+        Stdio::inherit()
+    };
+
+    match escalation {
+        Some(escalation) => {
+            escalation.push(&manager.name, &args);
+            Ok(None)
+        }
+        None => manager::run_with_stdin(&manager.name, args, stdin).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Escalation;
+    use crate::configs::conflict::Outcome;
+    use crate::configs::path::{ArchivePath, PathKind};
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_escalation() {
+        let mut escalation =
+            Escalation::new(vec!["env".to_string()], PathBuf::from("/home/alice")).unwrap();
+
+        let etc = ArchivePath {
+            kind: PathKind::ABSOLUTE,
+            path: Path::new("etc/hosts").into(),
+        };
+        let home = ArchivePath {
+            kind: PathKind::ABSOLUTE,
+            path: Path::new("home/alice/.bashrc").into(),
+        };
+        assert!(escalation.covers(&etc, Path::new("/etc/hosts")));
+        assert!(!escalation.covers(&home, Path::new("/home/alice/.bashrc")));
+
+        let dir = std::env::temp_dir().join(format!("rconf-escalation-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dst = dir.join("nested/file");
+
+        let staged = escalation.staged(&dst);
+        fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        fs::write(&staged, "content").unwrap();
+
+        escalation.replace(&dst, &Outcome::Written);
+        escalation.replace(&dir.join("skipped"), &Outcome::Skipped);
+        assert_eq!(2, escalation.steps.len());
+
+        let invocation = escalation.run().unwrap().unwrap();
+        assert_eq!("env", invocation.command);
+        assert_eq!("content", fs::read_to_string(&dst).unwrap());
+        assert!(escalation.run().unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_staging_dir() {
+        let escalation =
+            || Escalation::new(vec!["env".to_string()], PathBuf::from("/home/alice")).unwrap();
+        let (first, second) = (escalation(), escalation());
+
+        // every escalation stages into its own new directory which only the user can access
+        assert_ne!(first.staging, second.staging);
+
+        let meta = fs::symlink_metadata(&first.staging).unwrap();
+        assert!(meta.is_dir());
+        assert_eq!(0o700, meta.mode() & 0o777);
+        assert_eq!(unsafe { libc::geteuid() }, meta.uid());
+
+        let staging = first.staging.clone();
+        drop(first);
+        assert!(!staging.exists());
+    }
+}
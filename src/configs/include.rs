@@ -0,0 +1,115 @@
+use super::error::{ConfigError, Result};
+use super::path::expand_path;
+use super::ConfigArchive;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Resolve the location of an included file, which is expanded like a configured path (see
+/// [expand_path](../path/fn.expand_path.html)). Relative paths are relative to the directory of
+/// the including file.
+pub(crate) fn resolve(include: &str, dir: &Path) -> Result<PathBuf> {
+    Ok(dir.join(expand_path(include)?))
+}
+
+/// Load a configuration file and every file it includes, the including files are given by the
+/// chain of their canonical paths.
+fn load_chain(path: &Path, chain: &mut Vec<PathBuf>) -> Result<ConfigArchive> {
+    let canonical = fs::canonicalize(path)?;
+
+    if let Some(start) = chain.iter().position(|including| including == &canonical) {
+        let mut cycle = chain[start..].to_vec();
+        cycle.push(canonical);
+
+        return Err(ConfigError::IncludeCycle(cycle));
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let cfg: ConfigArchive = toml::from_str(&contents)?;
+
+    if cfg.include.is_empty() {
+        return Ok(cfg);
+    }
+
+    let dir = canonical
+        .parent()
+        .unwrap_or_else(|| Path::new("/"))
+        .to_path_buf();
+    chain.push(canonical);
+
+    let mut merged = ConfigArchive::new(None, None);
+    for include in &cfg.include {
+        merged.extend(&load_chain(&resolve(include, &dir)?, chain)?);
+    }
+
+    chain.pop();
+    merged.extend(&cfg);
+
+    Ok(merged)
+}
+
+/// Load a configuration file, merging the configuration of every file it includes before its own.
+/// See [extend](../struct.ConfigArchive.html#method.extend).
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading or parsing
+/// any of the files, or if the files include each other.
+pub fn load(path: &Path) -> Result<ConfigArchive> {
+    load_chain(path, &mut vec![])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load;
+    use crate::configs::conflict::ConflictPolicy;
+    use crate::configs::error::ConfigError;
+    use crate::configs::path::PathEntry;
+    use std::fs;
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("rconf-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("team")).unwrap();
+
+        fs::write(
+            dir.join("team/base.toml"),
+            "paths.home = ['.bashrc']
+manager.name = 'pacman'
+manager.install_args = ['-S']
+manager.packages = ['git']",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("work.toml"),
+            "include = ['team/base.toml']
+paths.home = ['.ssh/config', { path = '.bashrc', optional = true, on_conflict = 'backup' }]
+manager.packages = ['fish']",
+        )
+        .unwrap();
+        fs::write(dir.join("cycle.toml"), "include = ['cycle.toml']").unwrap();
+
+        let cfg = load(&dir.join("work.toml")).unwrap();
+        let paths = cfg.paths.unwrap();
+        let manager = cfg.manager.unwrap();
+
+        // the including file's options for a path replace those of the included file
+        let bashrc = PathEntry {
+            optional: true,
+            on_conflict: Some(ConflictPolicy::Backup),
+            ..".bashrc".into()
+        };
+        assert_eq!(Some(vec![bashrc, ".ssh/config".into()]), paths.home);
+        assert_eq!("pacman", manager.name);
+        assert_eq!(
+            vec!["git".to_string(), "fish".to_string()],
+            manager.packages
+        );
+        assert!(cfg.include.is_empty());
+
+        match load(&dir.join("cycle.toml")) {
+            Err(ConfigError::IncludeCycle(cycle)) => assert_eq!(2, cycle.len()),
+            _ => panic!("expected an include cycle"),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
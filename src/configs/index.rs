@@ -0,0 +1,206 @@
+use super::error::Result;
+use super::ConfigArchive;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::{Entry, EntryType};
+
+/// A summary of the type and content of an archived entry used to detect changes between entries.
+///
+/// Digests are only comparable within the same process and are never stored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Digest(u64);
+
+/// Adapter feeding written bytes into a hasher so content can be copied into it.
+struct HashWriter<'a>(&'a mut DefaultHasher);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Digest {
+    /// Compute the digest of a directory, all directories have the same digest.
+    pub fn of_dir() -> Digest {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u8(b'd');
+
+        Digest(hasher.finish())
+    }
+
+    /// Compute the digest of a file's content.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// content.
+    pub fn of_content<R: Read>(mut content: R) -> Result<Digest> {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u8(b'f');
+
+        io::copy(&mut content, &mut HashWriter(&mut hasher))?;
+
+        Ok(Digest(hasher.finish()))
+    }
+
+    /// Combine the digest with the permission bits of the entry, so that entries whose mode
+    /// changed differ even when their content does not.
+    pub fn with_mode(self, mode: u32) -> Digest {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u64(self.0);
+        hasher.write_u32(mode & 0o7777);
+
+        Digest(hasher.finish())
+    }
+
+    /// Compute the digest of an archive entry including its mode, consuming its content.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// entry.
+    pub fn of_entry<R: Read>(entry: &mut Entry<R>) -> Result<Digest> {
+        let mode = entry.header().mode()?;
+
+        match entry.header().entry_type() {
+            EntryType::Directory => Ok(Digest::of_dir().with_mode(mode)),
+            EntryType::Symlink | EntryType::Link => {
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u8(b'l');
+
+                if let Some(link) = entry.link_name()? {
+                    hasher.write(link.to_string_lossy().as_bytes());
+                }
+
+                Ok(Digest(hasher.finish()))
+            }
+            _ => Ok(Digest::of_content(entry)?.with_mode(mode)),
+        }
+    }
+}
+
+/// The differences between the entries of two [ArchiveIndex](struct.ArchiveIndex.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexDiff {
+    /// Entries in both indices whose content differs.
+    pub changed: Vec<PathBuf>,
+
+    /// Entries only in the newer index.
+    pub added: Vec<PathBuf>,
+
+    /// Entries only in the older index.
+    pub vanished: Vec<PathBuf>,
+}
+
+impl IndexDiff {
+    /// Whether there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.vanished.is_empty()
+    }
+}
+
+/// The [Digest](struct.Digest.html) of every configuration entry in an archive by its path in the
+/// archive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchiveIndex {
+    pub digests: BTreeMap<PathBuf, Digest>,
+}
+
+impl ArchiveIndex {
+    /// Index the configuration entries of a tar archive by their current path in the archive,
+    /// ignoring the manifest and generated scripts, see
+    /// [for_each_entry](../struct.ConfigArchive.html#method.for_each_entry).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// archive or parsing its manifest.
+    pub fn with_archive<P: AsRef<Path>>(path: P) -> Result<ArchiveIndex> {
+        let mut digests = BTreeMap::new();
+
+        ConfigArchive::with_plain_archive(path)?.for_each_entry(|path, entry| {
+            digests.insert(path.to_tar_path(), Digest::of_entry(entry)?);
+
+            Ok(())
+        })?;
+
+        Ok(ArchiveIndex { digests })
+    }
+
+    /// Compare this index against a newer index.
+    pub fn diff(&self, newer: &ArchiveIndex) -> IndexDiff {
+        let mut diff = IndexDiff::default();
+
+        for (path, digest) in &newer.digests {
+            match self.digests.get(path) {
+                Some(old) if old != digest => diff.changed.push(path.clone()),
+                Some(_) => {}
+                None => diff.added.push(path.clone()),
+            }
+        }
+
+        diff.vanished = self
+            .digests
+            .keys()
+            .filter(|path| !newer.digests.contains_key(*path))
+            .cloned()
+            .collect();
+
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveIndex, Digest};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_digest_content() {
+        assert_eq!(
+            Digest::of_content("abc".as_bytes()).unwrap(),
+            Digest::of_content("abc".as_bytes()).unwrap()
+        );
+        assert_ne!(
+            Digest::of_content("abc".as_bytes()).unwrap(),
+            Digest::of_content("abd".as_bytes()).unwrap()
+        );
+        assert_ne!(Digest::of_dir(), Digest::of_content("".as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_digest_mode() {
+        let digest = Digest::of_content("abc".as_bytes()).unwrap();
+
+        assert_eq!(digest.with_mode(0o644), digest.with_mode(0o100644));
+        assert_ne!(digest.with_mode(0o644), digest.with_mode(0o755));
+        assert_ne!(digest, digest.with_mode(0o644));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = ArchiveIndex::default();
+        let mut new = ArchiveIndex::default();
+
+        let a = Digest::of_content("a".as_bytes()).unwrap();
+        let b = Digest::of_content("b".as_bytes()).unwrap();
+
+        old.digests.insert(PathBuf::from("home/same"), a);
+        old.digests.insert(PathBuf::from("home/changed"), a);
+        old.digests.insert(PathBuf::from("home/vanished"), a);
+        new.digests.insert(PathBuf::from("home/same"), a);
+        new.digests.insert(PathBuf::from("home/changed"), b);
+        new.digests.insert(PathBuf::from("home/added"), b);
+
+        let diff = old.diff(&new);
+
+        assert_eq!(vec![PathBuf::from("home/changed")], diff.changed);
+        assert_eq!(vec![PathBuf::from("home/added")], diff.added);
+        assert_eq!(vec![PathBuf::from("home/vanished")], diff.vanished);
+    }
+}
@@ -1,40 +1,199 @@
 use crate::configs::error::{ConfigError, Result};
-use std::process::{Command, ExitStatus};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The placeholder in the root arguments replaced by the alternate root directory.
+pub const ROOT_PLACEHOLDER: &str = "{root}";
+
+/// The number of trailing lines of a command's stderr kept for error reports.
+pub(crate) const STDERR_TAIL: usize = 10;
+
+/// A successful run of the package manager, or of another command run by rconf.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Invocation {
+    pub command: String,
+    pub args: Vec<String>,
+    pub exit_code: Option<i32>,
+}
+
+/// Run a command with the given arguments, forwarding its stderr while keeping the last lines for
+/// the error returned if it fails. Its stdout is also written to stderr so that stdout is left for
+/// rconf's own report.
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned if the command could not be
+/// run or exited unsuccessfully.
+pub(crate) fn run(command: &str, args: Vec<String>) -> Result<Invocation> {
+    run_with_stdin(command, args, Stdio::inherit())
+}
+
+/// Run a command with the given stdin, see [run](fn.run.html).
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned if the command could not be
+/// run or exited unsuccessfully.
+pub(crate) fn run_with_stdin(command: &str, args: Vec<String>, stdin: Stdio) -> Result<Invocation> {
+    let spawn_error = |err| ConfigError::Spawn {
+        command: command.to_string(),
+        args: args.clone(),
+        source: err,
+    };
+
+    let mut child = Command::new(command)
+        .args(&args)
+        .stdin(stdin)
+        .stdout(io::stderr())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    let mut tail: VecDeque<String> = VecDeque::with_capacity(STDERR_TAIL);
+
+    if let Some(stderr) = child.stderr.take() {
+        let mut stderr = BufReader::new(stderr);
+        let mut line = vec![];
+
+        // the command's output is not necessarily UTF-8
+        while stderr.read_until(b'\n', &mut line)? > 0 {
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(&['\r', '\n'][..]).to_string();
+            eprintln!("{}", text);
+
+            if tail.len() == STDERR_TAIL {
+                tail.pop_front();
+            }
+            tail.push_back(text);
+            line.clear();
+        }
+    }
+
+    let status = child.wait().map_err(spawn_error)?;
+
+    if status.success() {
+        Ok(Invocation {
+            command: command.to_string(),
+            args,
+            exit_code: status.code(),
+        })
+    } else {
+        Err(ConfigError::Exit {
+            command: command.to_string(),
+            args,
+            code: status.code(),
+            stderr: Vec::from(tail).join("\n"),
+        })
+    }
+}
 
 /// Describes package manager commands to install and uninstall packages as well as upgrade the
 /// system.
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Manager {
     /// The name of the package manager (pacman, yum, apt, etc)
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub packages: Vec<String>,
+    #[serde(default)]
     pub install_args: Vec<String>,
-    un_install_args: Option<Vec<String>>,
-    upgrade_args: Option<Vec<String>>,
+
+    /// The arguments uninstalling the packages, if they can be uninstalled.
+    pub un_install_args: Option<Vec<String>>,
+
+    /// The arguments upgrading the system, which are required to upgrade when installing.
+    pub upgrade_args: Option<Vec<String>>,
+
+    /// The arguments directing the package manager to operate on an alternate root directory,
+    /// where `{root}` is replaced by the directory (ex `["--root", "{root}"]`).
+    pub root_args: Option<Vec<String>>,
 }
 
 impl Manager {
+    /// Combine another manager into this one, the other manager's command and arguments take
+    /// precedence while its packages are added to those already specified. A manager without a
+    /// name only adds its packages and arguments.
+    pub fn extend(&mut self, other: &Manager) {
+        if !other.name.is_empty() {
+            self.name = other.name.clone();
+            self.install_args = other.install_args.clone();
+        }
+
+        if other.un_install_args.is_some() {
+            self.un_install_args = other.un_install_args.clone();
+        }
+
+        if other.upgrade_args.is_some() {
+            self.upgrade_args = other.upgrade_args.clone();
+        }
+
+        if other.root_args.is_some() {
+            self.root_args = other.root_args.clone();
+        }
+
+        for package in &other.packages {
+            if !self.packages.contains(package) {
+                self.packages.push(package.clone());
+            }
+        }
+    }
+
+    /// Retrieve a manager operating on the given alternate root directory, by adding its root
+    /// arguments before the arguments of every command.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if no root arguments are
+    /// specified.
+    pub fn in_root(&self, root: &Path) -> Result<Manager> {
+        let root_args: Vec<String> = match &self.root_args {
+            Some(args) => args
+                .iter()
+                .map(|arg| arg.replace(ROOT_PLACEHOLDER, &root.to_string_lossy()))
+                .collect(),
+            None => return Err(ConfigError::FieldNotFound("manager.root_args".to_string())),
+        };
+
+        let with_root = |args: &Vec<String>| root_args.iter().chain(args).cloned().collect();
+
+        Ok(Manager {
+            install_args: with_root(&self.install_args),
+            un_install_args: self.un_install_args.as_ref().map(with_root),
+            upgrade_args: self.upgrade_args.as_ref().map(with_root),
+            root_args: None,
+            ..self.clone()
+        })
+    }
+
     /// Install the packages specified using the specified package manager.
-    pub fn install_packages(&self) -> ExitStatus {
-        Command::new(&self.name)
-            .args(&self.install_args)
-            .args(&self.packages)
-            .spawn()
-            .expect("Could not run the package manager with the given args")
-            .wait()
-            .expect("Issue waiting for the child installing process")
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if the package manager
+    /// could not be run or exited unsuccessfully.
+    pub fn install_packages(&self) -> Result<Invocation> {
+        run(&self.name, self.install_command())
+    }
+
+    /// Retrieve the arguments installing the packages.
+    pub fn install_command(&self) -> Vec<String> {
+        self.install_args
+            .iter()
+            .chain(&self.packages)
+            .cloned()
+            .collect()
     }
 
     /// Uninstall the packages specified using the  specified package manager.
-    pub fn un_install_packages(&self) -> Result<ExitStatus> {
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if no uninstall arguments
+    /// are specified, or the package manager could not be run or exited unsuccessfully.
+    pub fn un_install_packages(&self) -> Result<Invocation> {
         if let Some(args) = &self.un_install_args {
-            Ok(Command::new(&self.name)
-                .args(args)
-                .args(&self.packages)
-                .spawn()
-                .expect("Could not run the package manager with the given args")
-                .wait()
-                .expect("Issue waiting for the child installing process"))
+            run(
+                &self.name,
+                args.iter().chain(&self.packages).cloned().collect(),
+            )
         } else {
             Err(ConfigError::FieldNotFound("un_install_args".to_string()))
         }
@@ -42,14 +201,22 @@ impl Manager {
 
     /// Upgrade the current machine, it is suggested that the user reboots their computer after this
     /// is executed but it is not enforced.
-    pub fn system_upgrade(&self) -> Result<ExitStatus> {
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if no upgrade arguments
+    /// are specified, or the package manager could not be run or exited unsuccessfully.
+    pub fn system_upgrade(&self) -> Result<Invocation> {
+        run(&self.name, self.upgrade_command()?)
+    }
+
+    /// Retrieve the arguments upgrading the system.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if no upgrade arguments
+    /// are specified.
+    pub fn upgrade_command(&self) -> Result<Vec<String>> {
         if let Some(args) = &self.upgrade_args {
-            Ok(Command::new(&self.name)
-                .args(args)
-                .spawn()
-                .expect("Could not run the package manager with the given args")
-                .wait()
-                .expect("Issue waiting for the child installing process"))
+            Ok(args.clone())
         } else {
             Err(ConfigError::FieldNotFound(
                 "manager.upgrade_args".to_string(),
@@ -57,3 +224,85 @@ impl Manager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{run_with_stdin, Manager};
+    use crate::configs::error::ConfigError;
+    use std::path::Path;
+    use std::process::Stdio;
+
+    fn manager(name: &str, install_args: &[&str]) -> Manager {
+        Manager {
+            name: name.to_string(),
+            packages: vec![],
+            install_args: install_args.iter().map(|arg| arg.to_string()).collect(),
+            un_install_args: None,
+            upgrade_args: None,
+            root_args: None,
+        }
+    }
+
+    #[test]
+    fn test_in_root() {
+        let mut manager = manager("pacman", &["-S"]);
+        manager.upgrade_args = Some(vec!["-Syu".to_string()]);
+
+        assert!(manager.in_root(Path::new("/mnt")).is_err());
+
+        manager.root_args = Some(vec!["--sysroot={root}".to_string()]);
+        let rooted = manager.in_root(Path::new("/mnt")).unwrap();
+
+        assert_eq!(vec!["--sysroot=/mnt", "-S"], rooted.install_args);
+        assert_eq!(
+            Some(vec!["--sysroot=/mnt".to_string(), "-Syu".to_string()]),
+            rooted.upgrade_args
+        );
+        assert!(rooted.un_install_args.is_none());
+    }
+
+    #[test]
+    fn test_missing_manager() {
+        match manager("rconf-missing-manager", &[]).install_packages() {
+            Err(ConfigError::Spawn { command, .. }) => assert_eq!("rconf-missing-manager", command),
+            _ => panic!("expected a spawn error"),
+        }
+    }
+
+    #[test]
+    fn test_failed_manager() {
+        let manager = manager("sh", &["-c", "echo first >&2; echo last >&2; exit 3"]);
+
+        match manager.install_packages() {
+            Err(ConfigError::Exit { code, stderr, .. }) => {
+                assert_eq!(Some(3), code);
+                assert_eq!("first\nlast", stderr);
+            }
+            _ => panic!("expected an exit error"),
+        }
+    }
+
+    #[test]
+    fn test_non_utf8_stderr() {
+        let manager = manager("sh", &["-c", "printf 'caf\\351\\n' >&2; exit 3"]);
+
+        match manager.install_packages() {
+            Err(ConfigError::Exit { code, stderr, .. }) => {
+                assert_eq!(Some(3), code);
+                assert_eq!("caf\u{FFFD}", stderr);
+            }
+            _ => panic!("expected an exit error"),
+        }
+    }
+
+    #[test]
+    fn test_run_without_stdin() {
+        // a command reading stdin sees its end rather than waiting for or consuming rconf's input
+        let invocation = run_with_stdin(
+            "sh",
+            vec!["-c".to_string(), "! read line".to_string()],
+            Stdio::null(),
+        );
+        assert_eq!(Some(0), invocation.unwrap().exit_code);
+    }
+}
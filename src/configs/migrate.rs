@@ -0,0 +1,164 @@
+use super::error::{ConfigError, Result};
+use super::path::ArchivePath;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// The version of the manifest format written by this version of rconf. It is incremented with
+/// every change to the manifest which would be misread by older versions, along with a migration
+/// from the previous version in [migrate](fn.migrate.html).
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The version of rconf recorded in the manifests it writes.
+pub const RCONF_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The key of the manifest's format version, manifests written before versioning have none.
+const FORMAT_KEY: &str = "format_version";
+
+/// The key of the version of rconf which wrote the manifest.
+const WRITER_KEY: &str = "written_by";
+
+/// Record the current format and rconf version in a manifest.
+pub fn stamp(manifest: &mut Table) {
+    manifest.insert(
+        FORMAT_KEY.to_string(),
+        Value::Integer(FORMAT_VERSION.into()),
+    );
+    manifest.insert(
+        WRITER_KEY.to_string(),
+        Value::String(RCONF_VERSION.to_string()),
+    );
+}
+
+/// The first format storing absolute paths beneath their own directory in the archive, older
+/// archives store them at the archive root, see
+/// [from_legacy_tar_path](../path/struct.ArchivePath.html#method.from_legacy_tar_path).
+pub const ABSOLUTE_DIR_VERSION: u64 = 2;
+
+/// Migrate a manifest written in an older format to the current format, one version at a time,
+/// and return the version it was written in.
+///
+/// Only the manifest is migrated, the entries of an archive written before
+/// [ABSOLUTE_DIR_VERSION](constant.ABSOLUTE_DIR_VERSION.html) are mapped to their current paths
+/// when they are read.
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) will be returned if the manifest's format is
+/// newer than the current format or its version is invalid.
+pub fn migrate(manifest: &mut Table) -> Result<u64> {
+    let written_by = manifest
+        .get(WRITER_KEY)
+        .and_then(Value::as_str)
+        .map(String::from);
+
+    let version = match manifest.get(FORMAT_KEY) {
+        None => 0,
+        Some(Value::Integer(version)) if *version >= 0 => *version as u64,
+        Some(version) => return Err(ConfigError::InvalidFormat(version.to_string())),
+    };
+
+    if version > FORMAT_VERSION.into() {
+        return Err(ConfigError::UnsupportedFormat {
+            version,
+            written_by,
+        });
+    }
+
+    for from in version..FORMAT_VERSION.into() {
+        match from {
+            // manifests written before versioning share the layout of the first versioned format,
+            // while their entries are read in their own layout
+            0 => {}
+            1 => prefix_absolute_paths(manifest, version),
+            _ => unreachable!("no migration from format version {}", from),
+        }
+    }
+
+    manifest.insert(
+        FORMAT_KEY.to_string(),
+        Value::Integer(FORMAT_VERSION.into()),
+    );
+
+    Ok(version)
+}
+
+/// Move the absolute paths removed by an incremental archive beneath the archive's absolute
+/// directory, they were stored at the archive root before format version 2. The paths are read
+/// in the layout of the version the manifest was written in.
+fn prefix_absolute_paths(manifest: &mut Table, written: u64) {
+    let removed = manifest
+        .get_mut("incremental")
+        .and_then(|incremental| incremental.get_mut("removed"))
+        .and_then(Value::as_array_mut);
+
+    for removed in removed.into_iter().flatten() {
+        let tar_path = removed
+            .as_str()
+            .and_then(|path| match written {
+                0 => ArchivePath::from_unversioned_tar_path(Path::new(path)),
+                _ => ArchivePath::from_legacy_tar_path(Path::new(path)),
+            })
+            .map(|path| path.to_tar_path());
+
+        if let Some(tar_path) = tar_path {
+            *removed = Value::String(tar_path.to_string_lossy().to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, stamp, FORMAT_VERSION};
+    use crate::configs::error::ConfigError;
+    use toml::Table;
+
+    #[test]
+    fn test_migrate() {
+        let mut unversioned: Table = toml::from_str("paths.home = ['.bashrc']").unwrap();
+        migrate(&mut unversioned).unwrap();
+        assert_eq!(
+            Some(FORMAT_VERSION as i64),
+            unversioned["format_version"].as_integer()
+        );
+
+        let mut current = Table::new();
+        stamp(&mut current);
+        migrate(&mut current).unwrap();
+
+        let mut newer: Table =
+            toml::from_str("format_version = 1000\nwritten_by = '9.0.0'").unwrap();
+
+        match migrate(&mut newer) {
+            Err(ConfigError::UnsupportedFormat {
+                version,
+                written_by,
+            }) => {
+                assert_eq!(1000, version);
+                assert_eq!(Some("9.0.0".to_string()), written_by);
+            }
+            _ => panic!("a newer format should not be read"),
+        }
+
+        for invalid in &["format_version = -1", "format_version = 'two'"] {
+            match migrate(&mut toml::from_str(invalid).unwrap()) {
+                Err(err @ ConfigError::InvalidFormat(_)) => {
+                    assert_eq!("invalid_format", err.code())
+                }
+                _ => panic!("an invalid format version should not be read"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_migrate_absolute_paths() {
+        let mut manifest: Table = toml::from_str(
+            "format_version = 1\n[incremental]\nbase = 'base.tar'\nremoved = ['etc/hosts', 'bin/foo']",
+        )
+        .unwrap();
+
+        assert_eq!(1, migrate(&mut manifest).unwrap());
+        assert_eq!(
+            toml::Value::try_from(vec!["absolute/etc/hosts", "bin/foo"]).unwrap(),
+            manifest["incremental"]["removed"]
+        );
+    }
+}
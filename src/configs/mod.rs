@@ -1,135 +1,675 @@
 #[macro_use]
 pub mod path;
+pub mod builder;
+pub mod conflict;
 pub mod error;
+pub mod escalate;
+pub mod include;
+pub mod index;
 pub mod manager;
+pub mod migrate;
+pub mod overlay;
+pub mod state;
+pub mod user;
 
+use self::conflict::{unpack_with_policy, ConflictPolicies, ConflictPolicy, Outcome};
 use self::error::{ConfigError, Result};
+use self::escalate::{run_manager, Escalation};
+use self::state::InstallState;
+use self::user::User;
+use self::index::{ArchiveIndex, Digest, IndexDiff};
 use self::manager::*;
 use self::path::*;
-use super::script::build_script;
+use super::script::{build_install_script, build_uninstall_script};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::io::Read;
-use std::path::Path;
-use tar::{Archive, Builder, Header};
+use std::io::{self, Cursor, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tar::{Archive, Builder, Entry, Header};
+
+/// The name of the archive entry containing the serialized [ConfigArchive](struct.ConfigArchive.html).
+pub const MANIFEST: &str = ".rconf";
+
+/// The name of the archive entry containing the generated installer script.
+pub const INSTALL_SCRIPT: &str = "install.sh";
+
+/// The name of the archive entry containing the generated uninstaller script.
+pub const UNINSTALL_SCRIPT: &str = "uninstall.sh";
+
+/// The name of the archive directory containing the paths of every named root.
+pub const ROOTS: &str = "roots";
 
 /// Simple macro for generating a header for project files to be including in the configuration tar.
 macro_rules! basic_header {
-    ($data: expr) => {{
+    ($data: expr) => {
+        basic_header!($data, 420) // 644 (rw- r-- r--)
+    };
+    ($data: expr, $mode: expr) => {{
         let mut header = Header::new_gnu();
         header.set_size($data.len() as u64);
-        header.set_mode(420); // 644 (rw- r-- r--)
+        header.set_mode($mode);
         header.set_cksum();
 
         header
     }};
 }
 
+/// A stream of archived entries, read from a file, stdin, or any other reader.
+pub type ArchiveReader = Box<dyn Read>;
+
+/// Describes an incremental archive which only contains the entries which changed since its base
+/// archive.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Incremental {
+    /// The file name of the base archive.
+    pub base: String,
+
+    /// The archive paths of entries in the base archive which no longer exist.
+    pub removed: Vec<String>,
+}
+
+/// How the paths of an archive's entries map to the paths they are installed to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Layout {
+    /// Absolute paths are stored beneath the archive's absolute directory.
+    #[default]
+    Current,
+
+    /// Absolute paths are stored at the archive root, as in archives written before format
+    /// version 2.
+    Legacy,
+
+    /// Only home and config paths are stored beneath their own directories, every other path is
+    /// absolute, as in archives written before the format was versioned.
+    Unversioned,
+
+    /// A plain tar may store absolute paths either way, those beneath the absolute directory
+    /// take precedence.
+    Plain,
+}
+
+/// Options controlling how local files are archived.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchiveOptions {
+    /// Fail when a configured path is neither a file nor a directory, instead of skipping it,
+    /// unless the path is marked as optional.
+    pub strict: bool,
+}
+
+/// The entries written to an archive, and the paths which were skipped because they do not exist
+/// or could not be archived.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchiveReport {
+    pub archived: Vec<ArchivedEntry>,
+    pub skipped: Vec<SkippedPath>,
+}
+
+/// The entries which differ after refreshing an archive, and the paths which were skipped because
+/// they do not exist or could not be archived.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpdateReport {
+    pub diff: IndexDiff,
+    pub skipped: Vec<SkippedPath>,
+}
+
+/// Options controlling how archived configurations are installed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstallOptions {
+    /// The policy for replacing existing local files, unless the configured path specifies its
+    /// own.
+    pub on_conflict: ConflictPolicy,
+
+    /// Merge the changes made to local files since they were last installed with the archived
+    /// files, see [InstallState](state/struct.InstallState.html). The installed content is only
+    /// recorded when merging.
+    pub merge: bool,
+
+    /// The local directories replacing those configured for named roots, see
+    /// [Roots](path/struct.Roots.html).
+    pub roots: BTreeMap<String, PathBuf>,
+
+    /// An alternate root directory to install beneath, such as a mounted system or image.
+    pub prefix: Option<PathBuf>,
+
+    /// An alternate home directory to install the user's files to.
+    pub home: Option<PathBuf>,
+
+    /// Install only the configuration files and not the packages.
+    pub skip_packages: bool,
+
+    /// Upgrade the system with the package manager before installing the packages.
+    pub upgrade: bool,
+
+    /// The command and arguments to run the package manager and write the paths outside of the
+    /// home directory with elevated privileges (ex `["sudo"]`), see
+    /// [Escalation](escalate/struct.Escalation.html).
+    pub escalate: Option<Vec<String>>,
+
+    /// The user given ownership of the files installed beneath the home directory, which is the
+    /// user's home directory unless another is given.
+    pub owner: Option<User>,
+
+    /// Whether the archive is read from stdin, which is then neither used to prompt for conflicts
+    /// nor given to the package manager.
+    pub archive_from_stdin: bool,
+
+    /// Whether the home directory is a skeleton copied into the home directory of new users (see
+    /// [SKEL](user/constant.SKEL.html)), beneath which no state is recorded so new users do not
+    /// receive a copy of it.
+    pub skeleton: bool,
+}
+
+impl InstallOptions {
+    /// Retrieve the state recording the content of installed files, which is only kept when
+    /// merging as the base of the next merge. There is no state when installing into a skeleton.
+    ///
+    /// # Errors
+    /// A [ConfigError](error/enum.ConfigError.html) will be returned if merging into a skeleton,
+    /// or if merging and the data directory could not be determined.
+    pub fn state(&self) -> Result<Option<InstallState>> {
+        if !self.merge {
+            return Ok(None);
+        } else if self.skeleton {
+            return Err(ConfigError::Argument(
+                "Cannot merge files installed into a skeleton, which has no install state"
+                    .to_string(),
+            ));
+        }
+
+        let data = self
+            .roots_for(&ConfigArchive::new(None, None))?
+            .dir_of(&PathKind::DATA)?;
+
+        Ok(Some(InstallState {
+            prefix: self.prefix.clone(),
+            owner: self.owner.clone(),
+            ..InstallState::in_data_dir(&data)
+        }))
+    }
+
+    /// Resolve the roots of a configuration to install to, beneath the alternate root and home
+    /// directories if any.
+    ///
+    /// # Errors
+    /// A [ConfigError](error/enum.ConfigError.html) will be returned if a configured root uses an
+    /// unset variable.
+    pub fn roots_for(&self, cfg: &ConfigArchive) -> Result<Roots> {
+        Ok(Roots {
+            prefix: self.prefix.clone(),
+            home: self
+                .home
+                .clone()
+                .or_else(|| self.owner.as_ref().map(|owner| owner.home.clone())),
+            ..cfg.roots(&self.roots)?
+        })
+    }
+
+    /// Retrieve the package manager to install packages with, which operates on the alternate root
+    /// directory if any, or none when packages are skipped.
+    ///
+    /// # Errors
+    /// A [ConfigError](error/enum.ConfigError.html) will be returned if installing beneath an
+    /// alternate root and the manager has no root arguments.
+    pub fn manager(&self, manager: Option<&Manager>) -> Result<Option<Manager>> {
+        match (manager, &self.prefix) {
+            (Some(_), _) if self.skip_packages => Ok(None),
+            (Some(manager), Some(prefix)) => Ok(Some(manager.in_root(prefix)?)),
+            (manager, _) => Ok(manager.cloned()),
+        }
+    }
+
+    /// Retrieve the escalation to run the privileged steps of an install with, or none if
+    /// privileges are not escalated or rconf already runs as root.
+    ///
+    /// # Errors
+    /// A [ConfigError](error/enum.ConfigError.html) will be returned if the escalation command is
+    /// empty, the home directory could not be determined, or on an error creating the staging
+    /// directory.
+    pub fn escalation(&self) -> Result<Option<Escalation>> {
+        match &self.escalate {
+            Some(_) if unsafe { libc::geteuid() } == 0 => Ok(None),
+            Some(command) => {
+                let home = self
+                    .roots_for(&ConfigArchive::new(None, None))?
+                    .dir_of(&PathKind::HOME)?;
+
+                Ok(Some(Escalation::new(command.clone(), home)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Collect the conflict policies of an archive's configured paths, see
+    /// [ConflictPolicies](conflict/struct.ConflictPolicies.html).
+    ///
+    /// # Errors
+    /// A [ConfigError](error/enum.ConfigError.html) will be returned if a configured path uses an
+    /// unset variable, or if any policy prompts while the archive is read from stdin.
+    fn policies(&self, paths: Option<&PathSpecifier>) -> Result<ConflictPolicies> {
+        let policies = ConflictPolicies::new(self.on_conflict, paths)?;
+
+        if self.archive_from_stdin && policies.uses(ConflictPolicy::Prompt) {
+            return Err(ConfigError::Argument(
+                "Cannot prompt for conflicts while reading the archive from stdin".to_string(),
+            ));
+        }
+
+        Ok(policies)
+    }
+
+    /// Upgrade the system if requested and install the packages of a package manager, or add
+    /// the commands to the escalated steps.
+    ///
+    /// # Errors
+    /// A [ConfigError](error/enum.ConfigError.html) will be returned if upgrading without a package
+    /// manager, or the package manager could not be run or exited unsuccessfully.
+    fn install_packages(
+        &self,
+        manager: Option<&Manager>,
+        mut escalation: Option<&mut Escalation>,
+    ) -> Result<Vec<Invocation>> {
+        let manager = match (self.manager(manager)?, self.upgrade) {
+            (Some(manager), _) => manager,
+            (None, true) => return Err(ConfigError::FieldNotFound(String::from("manager"))),
+            (None, false) => return Ok(vec![]),
+        };
+
+        let mut invocations = vec![];
+
+        if self.upgrade {
+            invocations.extend(run_manager(
+                &manager,
+                manager.upgrade_command()?,
+                escalation.as_deref_mut(),
+                self.archive_from_stdin,
+            )?);
+        }
+
+        invocations.extend(run_manager(
+            &manager,
+            manager.install_command(),
+            escalation,
+            self.archive_from_stdin,
+        )?);
+
+        Ok(invocations)
+    }
+}
+
+/// The outcome of uninstalling a [ConfigArchive](struct.ConfigArchive.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UninstallReport {
+    pub removed: Vec<ArchivedEntry>,
+    pub invocations: Vec<Invocation>,
+}
+
 /// A container struct for a [ConfigArchive](struct.ConfigArchive.html) and the archive which describes it.
 #[derive(Deserialize, Serialize)]
 pub struct ConfigArchive {
+    /// The version of the manifest's format, see [migrate](migrate/fn.migrate.html).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_version: Option<u32>,
+
+    /// The version of rconf which wrote the manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written_by: Option<String>,
+
     pub paths: Option<PathSpecifier>,
 
     pub manager: Option<Manager>,
 
+    /// The directories of the named roots which paths may be specified relative to, see
+    /// [Roots](path/struct.Roots.html).
+    #[serde(
+        default,
+        deserialize_with = "path::deserialize_roots",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub roots: BTreeMap<String, String>,
+
+    /// Other configuration files merged into this one, see
+    /// [with_file](struct.ConfigArchive.html#method.with_file).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incremental: Option<Incremental>,
+
     #[serde(skip)]
-    archive: Option<Archive<File>>,
+    archive: Option<Archive<ArchiveReader>>,
+
+    #[serde(skip)]
+    layout: Layout,
+}
+
+/// Parse the content of an archive's manifest, migrating it from an older format if needed.
+///
+/// # Errors
+/// A [ConfigError](error/enum.ConfigError.html) will be returned on an error parsing the manifest,
+/// or if it was written in a newer format.
+fn parse_manifest(content: &str) -> Result<ConfigArchive> {
+    let mut manifest: toml::Table = toml::from_str(content)?;
+    let version = migrate::migrate(&mut manifest)?;
+    let cfg: ConfigArchive = toml::Value::Table(manifest).try_into()?;
+
+    Ok(ConfigArchive {
+        layout: match version {
+            0 => Layout::Unversioned,
+            version if version < migrate::ABSOLUTE_DIR_VERSION => Layout::Legacy,
+            _ => Layout::Current,
+        },
+        ..cfg
+    })
+}
+
+/// Read the manifest from the first entry of an archive, returning its content along with the
+/// stream of the remaining entries. The entry is left in the stream if it is not the manifest.
+///
+/// # Errors
+/// A [ConfigError](error/enum.ConfigError.html) will be returned on an error reading from the
+/// stream.
+fn split_manifest(mut reader: ArchiveReader) -> Result<(Option<String>, ArchiveReader)> {
+    let mut header = Header::new_old();
+    reader.read_exact(header.as_mut_bytes())?;
+
+    if header.path()? != Path::new(MANIFEST) || !header.entry_type().is_file() {
+        let block = header.as_bytes().to_vec();
+        return Ok((None, Box::new(Cursor::new(block).chain(reader))));
+    }
+
+    let size = header.entry_size()?;
+    let mut content = String::new();
+    (&mut reader).take(size).read_to_string(&mut content)?;
+
+    // the content is padded to a whole number of blocks
+    let padding = (512 - size % 512) % 512;
+    io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+
+    Ok((Some(content), reader))
+}
+
+/// Write to a newly created file, removing it again if writing fails.
+///
+/// # Errors
+/// A [ConfigError](error/enum.ConfigError.html) will be returned on an error creating the file,
+/// or any error returned while writing it.
+fn write_file<T, F>(path: &Path, write: F) -> Result<T>
+where
+    F: FnOnce(File) -> Result<T>,
+{
+    let result = write(File::create(path)?);
+
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+
+    result
+}
+
+/// Replace a file atomically by writing its replacement to a temporary file alongside it, which is
+/// renamed over the file once written, so the file can be read while it is replaced. The
+/// temporary file is created with a name no other file has, and is given the permissions of the
+/// file it replaces. It is removed again if writing fails.
+///
+/// # Errors
+/// A [ConfigError](error/enum.ConfigError.html) will be returned on an error creating or renaming
+/// the temporary file, or any error returned while writing it.
+fn replace_file<T, F>(path: &Path, write: F) -> Result<T>
+where
+    F: FnOnce(&Path) -> Result<T>,
+{
+    static TMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let tmp = loop {
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(path.file_name().unwrap_or_default());
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = path.with_file_name(tmp_name);
+
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+        {
+            Ok(_) => break tmp,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    let result = write(&tmp).and_then(|written| {
+        if let Ok(meta) = fs::metadata(path) {
+            fs::set_permissions(&tmp, meta.permissions())?;
+        }
+
+        fs::rename(&tmp, path)?;
+        Ok(written)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
 }
 
 impl ConfigArchive {
-    /// Parse the archive's '.rconf' file as a [ConfigArchive](struct.ConfigArchive.html).
+    /// Parse the archive's '.rconf' file as a [ConfigArchive](struct.ConfigArchive.html), which is
+    /// the first entry of the archive so nothing else is read. Manifests written in an older
+    /// format, including those written before the format was versioned, are migrated to the
+    /// current format.
     ///
     /// # Errors
     /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
-    /// and parsing the configuration file.
-    fn retrieve_configs<P: AsRef<Path>>(path: P) -> Result<ConfigArchive> {
-        // read archive
-        let file = File::open(path)?;
-        let mut archive = Archive::new(file);
-        let entries = archive.entries()?;
-        let mut cfg: Option<ConfigArchive> = None;
-
-        // find configuration file
-        for entry in entries {
-            let mut entry = entry?;
-            if entry.path()?.to_str() == Some(".rconf") {
-                let mut content = String::new();
-                entry.read_to_string(&mut content)?;
-
-                cfg = Some(toml::from_str(content.as_str())?);
-            }
-        }
-
-        Ok(cfg.unwrap())
+    /// and parsing the configuration file, if it was written in a newer format, or if the archive
+    /// does not start with it.
+    pub fn retrieve_configs<P: AsRef<Path>>(path: P) -> Result<ConfigArchive> {
+        Ok(ConfigArchive {
+            archive: None,
+            ..ConfigArchive::with_archive(path)?
+        })
     }
 
-    /// Install all archived files to their intended locations on the file system.
+    /// Install all archived files to their intended locations on the file system, resolving
+    /// conflicts with existing local files as described by the options. Files needing privileges
+    /// are staged for the escalation, if any.
     ///
     /// # Errors
     /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
     /// the archive or unpacking a contained file to the specified location.
-    fn install_configs(&mut self) -> Result<()> {
-        if let Some(archive) = &mut self.archive {
-            let entries = archive.entries()?;
+    pub fn install_configs(
+        &mut self,
+        options: &InstallOptions,
+        mut escalation: Option<&mut Escalation>,
+    ) -> Result<()> {
+        let roots = options.roots_for(self)?;
 
-            for entry in entries {
-                let mut entry = entry?;
+        // remove first so that paths added back by merged layers are installed
+        self.remove_vanished(&roots, escalation.as_deref_mut())?;
 
-                // extract the path from the archive entry
-                let path = entry.path()?;
-                let path = match ArchivePath::from_tar_path(path.as_ref()) {
-                    None => continue,
-                    Some(p) => p,
-                };
+        let policies = options.policies(self.paths.as_ref())?;
+        let state = options.state()?;
+
+        self.for_each_entry(|path, entry| {
+            let policy = policies.for_path(&path.to_tar_path());
 
-                // retrieve the path's local location
-                let dst = path.to_local_path()?;
+            install_entry(
+                path,
+                &roots,
+                entry,
+                policy,
+                state.as_ref(),
+                options,
+                escalation.as_deref_mut(),
+            )
+            .map(|_| ())
+        })
+    }
 
-                entry.unpack(dst)?;
+    /// Resolve the configured roots, the given directories take precedence over those configured.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if a configured root uses
+    /// an unset variable.
+    pub fn roots(&self, overrides: &BTreeMap<String, PathBuf>) -> Result<Roots> {
+        Roots::new(&self.roots, overrides)
+    }
+
+    /// Remove the local files and directories of entries which were removed since the base of an
+    /// incremental archive.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if a file or directory
+    /// could not be removed.
+    fn remove_vanished(&self, roots: &Roots, mut escalation: Option<&mut Escalation>) -> Result<()> {
+        if let Some(incremental) = &self.incremental {
+            for removed in &incremental.removed {
+                if let Some(path) = ArchivePath::from_tar_path(Path::new(removed)) {
+                    remove_local(&path, roots, escalation.as_deref_mut())?;
+                }
             }
+        }
 
-            Ok(())
-        } else {
-            Ok(())
+        Ok(())
+    }
+
+    /// Create a new [ConfigArchive](struct.ConfigArchive.html) from its paths and manager which is
+    /// not backed by an archive.
+    pub fn new(paths: Option<PathSpecifier>, manager: Option<Manager>) -> ConfigArchive {
+        ConfigArchive {
+            format_version: None,
+            written_by: None,
+            paths,
+            manager,
+            roots: BTreeMap::new(),
+            include: vec![],
+            incremental: None,
+            archive: None,
+            layout: Layout::Current,
+        }
+    }
+
+    /// Combine another configuration into this one. The paths, packages, and roots of both are
+    /// specified, while the options of its paths, the package manager command and arguments, and
+    /// the root directories of the other take precedence.
+    pub fn extend(&mut self, other: &ConfigArchive) {
+        for (name, dir) in &other.roots {
+            self.roots.insert(name.clone(), dir.clone());
+        }
+
+        if let Some(paths) = &other.paths {
+            match &mut self.paths {
+                Some(combined) => combined.extend(paths),
+                None => self.paths = Some(paths.clone()),
+            }
+        }
+
+        if let Some(manager) = &other.manager {
+            match &mut self.manager {
+                Some(combined) => combined.extend(manager),
+                None => self.manager = Some(manager.clone()),
+            }
         }
     }
 
-    /// Uninstall and remove all specified configuration files.
+    /// Copy the configuration of this archive for use as the manifest of a new archive.
+    fn manifest(&self) -> ConfigArchive {
+        ConfigArchive {
+            roots: self.roots.clone(),
+            ..ConfigArchive::new(self.paths.clone(), self.manager.clone())
+        }
+    }
+
+    /// Visit every archived configuration file along with its location in the archive. The
+    /// manifest and generated scripts are not visited, and the entries of archives written in an
+    /// older format are visited at their current location.
     ///
     /// # Errors
-    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if a config file /
-    /// directory could not be remove or found.
-    fn uninstall_configs(&mut self) -> Result<()> {
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
+    /// the archive, or any error returned by the visitor.
+    pub fn for_each_entry<F>(&mut self, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&ArchivePath, &mut Entry<ArchiveReader>) -> Result<()>,
+    {
         if let Some(archive) = &mut self.archive {
-            let entries = archive.entries()?;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
 
-            for entry in entries {
-                let entry = entry?;
+                // plain archives may store their entries beneath '.', which is never visited
+                let tar_path = entry.path()?.to_path_buf();
+                let tar_path = tar_path.strip_prefix(".").unwrap_or(&tar_path);
 
-                let path = entry.path()?;
-                let path = match ArchivePath::from_tar_path(path.as_ref()) {
-                    None => continue,
-                    Some(p) => p,
+                if tar_path.as_os_str().is_empty() {
+                    continue;
+                }
+
+                let path = match self.layout {
+                    Layout::Current => ArchivePath::from_tar_path(tar_path),
+                    Layout::Legacy => ArchivePath::from_legacy_tar_path(tar_path),
+                    Layout::Unversioned => ArchivePath::from_unversioned_tar_path(tar_path),
+                    Layout::Plain => ArchivePath::from_tar_path(tar_path)
+                        .or_else(|| ArchivePath::from_legacy_tar_path(tar_path)),
                 };
 
-                let target = path.to_local_path()?;
-
-                match if target.is_file() {
-                    fs::remove_file(target)
-                } else if target.is_dir() {
-                    fs::remove_dir_all(target)
-                } else {
-                    Ok(())
-                } {
-                    Err(err) => return Err(ConfigError::from(err)),
-                    Ok(_) => (),
+                if let Some(path) = path {
+                    visit(&path, &mut entry)?;
                 }
             }
+        }
+
+        Ok(())
+    }
+
+    /// Unpack every archived configuration file beneath the given directory, preserving their
+    /// relative paths inside the archive (ex archive.tar/home/.bashrc => dst/home/.bashrc).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
+    /// the archive or writing an entry beneath the directory.
+    pub fn unpack_entries(&mut self, dst: &Path) -> Result<Vec<ArchivedEntry>> {
+        let mut unpacked = vec![];
+
+        self.for_each_entry(|path, entry| {
+            if entry.unpack_in(dst)? {
+                unpacked.push(ArchivedEntry {
+                    tar_path: path.to_tar_path(),
+                    is_dir: entry.header().entry_type().is_dir(),
+                });
+            }
 
             Ok(())
-        } else {
+        })?;
+
+        Ok(unpacked)
+    }
+
+    /// Uninstall and remove all specified configuration files, returning the removed entries.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if a config file /
+    /// directory could not be remove or found.
+    pub fn uninstall_configs(&mut self) -> Result<Vec<ArchivedEntry>> {
+        let roots = self.roots(&BTreeMap::new())?;
+        let mut removed = vec![];
+
+        self.for_each_entry(|path, entry| {
+            remove_local(path, &roots, None)?;
+            removed.push(ArchivedEntry {
+                tar_path: path.to_tar_path(),
+                is_dir: entry.header().entry_type().is_dir(),
+            });
+
             Ok(())
-        }
+        })?;
+
+        Ok(removed)
     }
 
     /// Construct a new [ConfigArchive](struct.ConfigArchive.html) from a tar archive.
@@ -138,76 +678,336 @@ impl ConfigArchive {
     /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from the archive, or
     /// parsing the contained archived file.
     pub fn with_archive<P: AsRef<Path>>(path: P) -> Result<ConfigArchive> {
-        let cfg = ConfigArchive::retrieve_configs(&path)?;
-        let file = File::open(path)?;
-        let archive = Some(Archive::new(file));
+        ConfigArchive::with_reader(File::open(path)?)
+    }
+
+    /// Construct a new [ConfigArchive](struct.ConfigArchive.html) from a tar archive read from a
+    /// stream such as stdin. The archive is read in a single pass, its manifest from the first
+    /// entry when constructed and the remaining entries when they are visited.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// manifest, or if the first entry is not the manifest.
+    pub fn with_reader<R: Read + 'static>(reader: R) -> Result<ConfigArchive> {
+        ConfigArchive::read(Box::new(reader), false)
+    }
+
+    /// Construct a new [ConfigArchive](struct.ConfigArchive.html) from a tar archive which may not
+    /// have been written by rconf. Without a manifest the archive is treated as a plain tar with
+    /// no configuration, whose entries are installed like archived paths (ex absolute/etc/hosts or
+    /// etc/hosts => /etc/hosts and home/.bashrc => $HOME/.bashrc).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// archive, or parsing its manifest if it has one.
+    pub fn with_plain_archive<P: AsRef<Path>>(path: P) -> Result<ConfigArchive> {
+        ConfigArchive::with_plain_reader(File::open(path)?)
+    }
+
+    /// Construct a new [ConfigArchive](struct.ConfigArchive.html) from a tar archive read from a
+    /// stream which may not have been written by rconf, see
+    /// [with_plain_archive](#method.with_plain_archive).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// archive, or parsing its manifest if it has one.
+    pub fn with_plain_reader<R: Read + 'static>(reader: R) -> Result<ConfigArchive> {
+        ConfigArchive::read(Box::new(reader), true)
+    }
+
+    /// Read the manifest from the first entry of an archive, leaving the remaining entries to be
+    /// visited. A plain archive has no configuration when it has no manifest.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading or
+    /// parsing the manifest, or if there is none and the archive is not plain.
+    fn read(reader: ArchiveReader, plain: bool) -> Result<ConfigArchive> {
+        let (manifest, rest) = split_manifest(reader)?;
+
+        let cfg = match manifest {
+            Some(content) => parse_manifest(&content)?,
+            None if plain => ConfigArchive {
+                layout: Layout::Plain,
+                ..ConfigArchive::new(None, None)
+            },
+            None => return Err(ConfigError::MissingManifest),
+        };
 
-        Ok(ConfigArchive { archive, ..cfg })
+        Ok(ConfigArchive {
+            archive: Some(Archive::new(rest)),
+            ..cfg
+        })
     }
 
     /// Create a new ConfigArchive instantiation from specified configuration file.
     ///
+    /// The files listed by its `include` key are loaded recursively and merged before the file
+    /// itself (see [extend](struct.ConfigArchive.html#method.extend)), so the file's package
+    /// manager takes precedence. Relative includes are relative to the including file.
+    ///
     /// # Errors
     /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
-    /// the specified file, or parsing the contents.
+    /// the specified or included files, parsing the contents, if the files include each other, or
+    /// if the merged package manager has no name.
     pub fn with_file(path: &Path) -> Result<ConfigArchive> {
-        let contents = fs::read_to_string(path)?;
-        let cfg: ConfigArchive = toml::from_str(&contents[..])?;
+        let cfg = include::load(path)?;
+
+        if let Some(manager) = &cfg.manager {
+            if manager.name.is_empty() {
+                return Err(ConfigError::FieldNotFound("manager.name".to_string()));
+            }
+        }
 
         Ok(cfg)
     }
 
-    /// Package configuration files into a tar archive and write to the system. See
-    /// [append_path_specifier]
+    /// Write the configuration as a toml configuration file.
     ///
     /// # Errors
-    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error creating the
-    /// archive, or adding files and their contents to it.
-    pub fn write_archive(&self, path: &Path) -> Result<File> {
-        let file = File::create(path)?;
-        let mut builder = Builder::new(file);
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error writing the
+    /// file.
+    pub fn write_config(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self).unwrap())?;
+
+        Ok(())
+    }
+
+    /// Write an archive with this configuration as its manifest containing the entries appended by
+    /// the given function, and return the appended entries.
+    ///
+    /// The manifest is always the first entry of the archive so that it can be read before the
+    /// configuration files in a single pass, see [with_reader](#method.with_reader). It is
+    /// followed by the configuration files and finally the generated `install.sh` and
+    /// `uninstall.sh` scripts which are built from the list of archived entries.
+    fn write_entries<W, F>(&self, writer: W, append: F) -> Result<Vec<ArchivedEntry>>
+    where
+        W: Write,
+        F: FnOnce(&mut Builder<W>) -> Result<Vec<ArchivedEntry>>,
+    {
+        let mut builder = Builder::new(writer);
+
+        // generate content and header for rconf file, recording the format it is written in
+        let mut manifest = match toml::Value::try_from(self).unwrap() {
+            toml::Value::Table(manifest) => manifest,
+            _ => unreachable!("a ConfigArchive is serialized as a table"),
+        };
+        migrate::stamp(&mut manifest);
 
-        // generate content and header for rconf file
-        let content = toml::to_string_pretty(self).unwrap();
-        let script = build_script(self);
+        let content = toml::to_string_pretty(&manifest).unwrap();
 
         builder.append_data(
             &mut basic_header!(content),
-            Path::new(".rconf"),
+            Path::new(MANIFEST),
             content.as_bytes(),
         )?;
+
+        // add the files from the specifier into the archive
+        let entries = append(&mut builder)?;
+
+        let roots = self.roots(&BTreeMap::new())?;
+        let install = build_install_script(self, &roots, &entries)?;
+        let uninstall = build_uninstall_script(self, &roots, &entries)?;
+
+        builder.append_data(
+            &mut basic_header!(install, 493), // 755 (rwx r-x r-x)
+            Path::new(INSTALL_SCRIPT),
+            install.as_bytes(),
+        )?;
         builder.append_data(
-            &mut basic_header!(script),
-            Path::new("install.sh"),
-            script.as_bytes(),
+            &mut basic_header!(uninstall, 493),
+            Path::new(UNINSTALL_SCRIPT),
+            uninstall.as_bytes(),
         )?;
 
-        // add the files from the specifier into the archive
-        if self.paths.is_some() {
-            builder.append_path_specifier(self.paths.as_ref().unwrap())?;
+        builder.into_inner()?;
+
+        Ok(entries)
+    }
+
+    /// Collect the local files and directories of the configured paths, see
+    /// [local_entries](path/struct.PathSpecifier.html#method.local_entries).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error collecting the
+    /// local files, or when strict and a path is skipped.
+    fn local_entries(&self, options: &ArchiveOptions) -> Result<LocalEntries> {
+        match &self.paths {
+            Some(paths) => paths.local_entries(&self.roots(&BTreeMap::new())?, options.strict),
+            None => Ok(LocalEntries::default()),
         }
+    }
 
-        Ok(builder.into_inner()?)
+    /// Package configuration files into a tar archive and write to the system, returning every
+    /// archived entry and every configured path which was skipped. See [append_path_specifier]
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error creating the
+    /// archive, or adding files and their contents to it. When strict, a path which is skipped is
+    /// an error, unless it is an optional configured path which does not exist.
+    pub fn write_archive(&self, path: &Path, options: &ArchiveOptions) -> Result<ArchiveReport> {
+        write_file(path, |file| self.write_archive_to(file, options))
     }
 
-    /// Install the configurations stored in the archive.
+    /// Package configuration files into a tar archive written to a stream such as stdout, see
+    /// [write_archive](#method.write_archive).
     ///
     /// # Errors
-    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error installing the
-    /// archived configurations.
-    pub fn install(&mut self) -> Result<()> {
-        if let Some(manager) = &self.manager {
-            let status = manager.install_packages();
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error writing the
+    /// archive, or adding files and their contents to it. When strict, a path which is skipped is
+    /// an error, unless it is an optional configured path which does not exist.
+    pub fn write_archive_to<W: Write>(
+        &self,
+        writer: W,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveReport> {
+        let collected = self.local_entries(options)?;
+
+        Ok(ArchiveReport {
+            archived: self.manifest().write_entries(writer, |builder| {
+                builder.append_local_entries(&collected.entries)
+            })?,
+            skipped: collected.skipped,
+        })
+    }
+
+    /// Package only the configuration files which differ from those in a base archive into an
+    /// incremental tar archive. Files whose content or mode changed are archived. The manifest of
+    /// the incremental archive lists the entries of the base archive which no longer exist so they
+    /// can be removed on install. Every archived entry and skipped path is returned.
+    ///
+    /// The base should be a full archive. Only its own entries are compared, so when it is itself
+    /// incremental the unchanged files are archived again, and entries which were removed since
+    /// the full archive it is based on are not listed as removed.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if the archive would be
+    /// written over the base archive, or on an error reading the base archive, creating the
+    /// archive, or adding files and their contents to it. When strict, a path which is skipped is
+    /// an error, unless it is an optional configured path which does not exist.
+    pub fn write_archive_since(
+        &self,
+        path: &Path,
+        base: &Path,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveReport> {
+        if let (Ok(written), Ok(base_meta)) = (fs::metadata(path), fs::metadata(base)) {
+            if written.dev() == base_meta.dev() && written.ino() == base_meta.ino() {
+                return Err(ConfigError::Argument(format!(
+                    "Cannot write the archive to {} which is its base archive",
+                    path.display()
+                )));
+            }
+        }
+
+        write_file(path, |file| self.write_archive_since_to(file, base, options))
+    }
+
+    /// Package only the configuration files which differ from those in a base archive into an
+    /// incremental tar archive written to a stream such as stdout, see
+    /// [write_archive_since](#method.write_archive_since).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// base archive, writing the archive, or adding files and their contents to it. When strict, a
+    /// path which is skipped is an error, unless it is an optional configured path which does not
+    /// exist.
+    pub fn write_archive_since_to<W: Write>(
+        &self,
+        writer: W,
+        base: &Path,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveReport> {
+        let base_index = ArchiveIndex::with_archive(base)?;
+
+        let LocalEntries { entries, skipped } = self.local_entries(options)?;
 
-            if !status.success() {
-                return Err(ConfigError::Manager(
-                    manager.name.clone(),
-                    manager.install_args.clone(),
-                ));
+        let mut changed = vec![];
+        for entry in &entries {
+            let digest = if entry.is_dir {
+                Digest::of_dir()
+            } else {
+                Digest::of_content(File::open(&entry.local)?)?
+            }
+            .with_mode(fs::metadata(&entry.local)?.mode());
+
+            if base_index.digests.get(&entry.tar_path) != Some(&digest) {
+                changed.push(entry.clone());
             }
         }
 
-        self.install_configs()?;
+        let removed = base_index
+            .digests
+            .keys()
+            .filter(|path| !entries.iter().any(|entry| &&entry.tar_path == path))
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+
+        let mut manifest = self.manifest();
+        manifest.incremental = Some(Incremental {
+            base: base
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            removed,
+        });
+
+        Ok(ArchiveReport {
+            archived: manifest
+                .write_entries(writer, |builder| builder.append_local_entries(&changed))?,
+            skipped,
+        })
+    }
+
+    /// Refresh an existing archive by collecting every path listed in its manifest from the local
+    /// system again. The archive is replaced atomically by writing the new archive alongside it
+    /// before renaming it over the original, keeping its permissions. An incremental archive stays
+    /// an incremental archive of the same base, removing the same paths. The differing entries and
+    /// the skipped paths are returned.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// existing archive or writing its replacement. When strict, a path which is skipped is an
+    /// error, unless it is an optional configured path which does not exist.
+    pub fn update_archive(path: &Path, options: &ArchiveOptions) -> Result<UpdateReport> {
+        let cfg = ConfigArchive::with_archive(path)?;
+        let old = ArchiveIndex::with_archive(path)?;
+
+        let mut manifest = cfg.manifest();
+        manifest.incremental = cfg.incremental.clone();
+        let LocalEntries { entries, skipped } = cfg.local_entries(options)?;
+
+        let new = replace_file(path, |tmp| {
+            manifest.write_entries(File::create(tmp)?, |builder| {
+                builder.append_local_entries(&entries)
+            })?;
+            ArchiveIndex::with_archive(tmp)
+        })?;
+
+        Ok(UpdateReport {
+            diff: old.diff(&new),
+            skipped,
+        })
+    }
+
+    /// Install the packages and configurations stored in the archive, see
+    /// [install_configs](#method.install_configs). The privileged steps are run together once
+    /// everything else was installed.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error installing the
+    /// packages or the archived configurations.
+    pub fn install(&mut self, options: &InstallOptions) -> Result<()> {
+        let mut escalation = options.escalation()?;
+
+        // the policies are checked before anything is installed
+        options.policies(self.paths.as_ref())?;
+        options.install_packages(self.manager.as_ref(), escalation.as_mut())?;
+        self.install_configs(options, escalation.as_mut())?;
+
+        if let Some(escalation) = &mut escalation {
+            escalation.run()?;
+        }
 
         Ok(())
     }
@@ -217,15 +1017,479 @@ impl ConfigArchive {
     /// # Errors
     /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error uninstalling
     /// the archived configurations.
-    pub fn uninstall(&mut self) -> Result<()> {
+    pub fn uninstall(&mut self) -> Result<UninstallReport> {
+        let mut invocations = vec![];
         if let Some(manager) = &self.manager {
-            if let Err(err) = manager.un_install_packages() {
-                return Err(err);
-            }
+            invocations.push(manager.un_install_packages()?);
         }
 
-        self.uninstall_configs()?;
+        Ok(UninstallReport {
+            removed: self.uninstall_configs()?,
+            invocations,
+        })
+    }
+}
 
-        Ok(())
+/// Remove the local file or directory of an archived path if it exists, or add its removal to the
+/// escalated steps if it needs privileges, and return its location.
+fn remove_local(path: &ArchivePath, roots: &Roots, escalation: Option<&mut Escalation>) -> Result<PathBuf> {
+    let target = path.to_local_path_in(roots)?;
+
+    match escalation {
+        Some(escalation) if escalation.covers(path, &target) => {
+            escalation.remove(&target);
+            return Ok(target);
+        }
+        _ => {}
+    }
+
+    if target.is_file() {
+        fs::remove_file(&target)?;
+    } else if target.is_dir() {
+        fs::remove_dir_all(&target)?;
+    }
+
+    Ok(target)
+}
+
+/// Install an archived entry to its intended location on the file system, creating any missing
+/// parent directories, and return the location along with what was done. Entries needing
+/// privileges are staged and copied to their location by the escalation.
+fn install_entry(
+    path: &ArchivePath,
+    roots: &Roots,
+    entry: &mut Entry<ArchiveReader>,
+    policy: ConflictPolicy,
+    state: Option<&InstallState>,
+    options: &InstallOptions,
+    escalation: Option<&mut Escalation>,
+) -> Result<(PathBuf, Outcome)> {
+    // retrieve the path's local location
+    let dst = path.to_local_path_in(roots)?;
+
+    let escalation = escalation.filter(|escalation| escalation.covers(path, &dst));
+    let staged = escalation.as_ref().map(|escalation| escalation.staged(&dst));
+
+    if let Some(parent) = staged.as_deref().unwrap_or(&dst).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let outcome = unpack_with_policy(
+        policy,
+        state,
+        options.merge,
+        entry,
+        &dst,
+        staged.as_deref(),
+    )?;
+
+    if let Some(escalation) = escalation {
+        escalation.replace(&dst, &outcome);
+    }
+
+    if let Some(owner) = &options.owner {
+        owner.own(&dst)?;
+    }
+
+    Ok((dst, outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::error::ConfigError;
+    use super::index::ArchiveIndex;
+    use super::path::PathSpecifier;
+    use super::{ArchiveOptions, ConfigArchive, InstallOptions};
+    use std::fs;
+    use std::io::Cursor;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::path::Path;
+    use tar::{Builder, Header};
+
+    #[test]
+    fn test_stream_archive() {
+        let dir = std::env::temp_dir().join(format!("rconf-stream-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("gitconfig");
+        fs::write(&file, "[user]").unwrap();
+
+        let paths = PathSpecifier {
+            absolute: Some(vec![file.to_string_lossy().to_string().into()]),
+            ..Default::default()
+        };
+
+        let mut stream = vec![];
+        ConfigArchive::new(Some(paths), None)
+            .write_archive_to(&mut stream, &ArchiveOptions::default())
+            .unwrap();
+
+        let mut cfg = ConfigArchive::with_reader(Cursor::new(stream)).unwrap();
+        assert!(cfg.paths.is_some());
+
+        fs::create_dir_all(dir.join("unpacked")).unwrap();
+        let unpacked = cfg.unpack_entries(&dir.join("unpacked")).unwrap();
+        let tar_path = Path::new("absolute").join(file.strip_prefix("/").unwrap());
+
+        assert_eq!(1, unpacked.len());
+        assert_eq!(tar_path, unpacked[0].tar_path);
+        assert_eq!(
+            "[user]",
+            fs::read_to_string(dir.join("unpacked").join(tar_path)).unwrap()
+        );
+        assert!(ConfigArchive::with_reader(Cursor::new(vec![0; 1024])).is_err());
+
+        // a failed archive is not left behind
+        let missing = PathSpecifier {
+            absolute: Some(vec![dir.join("missing").to_string_lossy().to_string().into()]),
+            ..Default::default()
+        };
+        let tar = dir.join("missing.tar");
+        let strict = ArchiveOptions { strict: true };
+
+        assert!(ConfigArchive::new(Some(missing), None)
+            .write_archive(&tar, &strict)
+            .is_err());
+        assert!(!tar.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_archive_since() {
+        let dir = std::env::temp_dir().join(format!("rconf-archive-since-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let files = ["same", "mode", "content", "removed"];
+        for file in &files {
+            fs::write(dir.join(file), file).unwrap();
+            fs::set_permissions(dir.join(file), fs::Permissions::from_mode(0o644)).unwrap();
+        }
+
+        let paths = PathSpecifier {
+            absolute: Some(
+                files
+                    .iter()
+                    .map(|file| dir.join(file).to_string_lossy().to_string().into())
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let cfg = ConfigArchive::new(Some(paths), None);
+        let options = ArchiveOptions::default();
+        let tar_path = |file| Path::new("absolute").join(dir.strip_prefix("/").unwrap().join(file));
+
+        let base = dir.join("base.tar");
+        cfg.write_archive(&base, &options).unwrap();
+
+        fs::set_permissions(dir.join("mode"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("content"), "changed").unwrap();
+        fs::remove_file(dir.join("removed")).unwrap();
+
+        let since = dir.join("since.tar");
+        let report = cfg.write_archive_since(&since, &base, &options).unwrap();
+        let archived: Vec<_> = report
+            .archived
+            .iter()
+            .map(|entry| &entry.tar_path)
+            .collect();
+
+        assert_eq!(vec![&tar_path("mode"), &tar_path("content")], archived);
+        assert_eq!(
+            vec![tar_path("removed").to_string_lossy().to_string()],
+            ConfigArchive::retrieve_configs(&since)
+                .unwrap()
+                .incremental
+                .unwrap()
+                .removed
+        );
+
+        // the base archive is not overwritten while it is read
+        assert!(cfg.write_archive_since(&base, &base, &options).is_err());
+        assert_eq!(4, ArchiveIndex::with_archive(&base).unwrap().digests.len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_archive() {
+        let dir = std::env::temp_dir().join(format!("rconf-update-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let (file, missing) = (dir.join("file"), dir.join("missing"));
+        fs::write(&file, "old").unwrap();
+
+        let paths = PathSpecifier {
+            absolute: Some(vec![
+                file.to_string_lossy().to_string().into(),
+                missing.to_string_lossy().to_string().into(),
+            ]),
+            ..Default::default()
+        };
+        let cfg = ConfigArchive::new(Some(paths), None);
+        let options = ArchiveOptions::default();
+
+        let base = dir.join("base.tar");
+        let since = dir.join("since.tar");
+        cfg.write_archive(&base, &options).unwrap();
+        fs::write(&file, "new").unwrap();
+        cfg.write_archive_since(&since, &base, &options).unwrap();
+        fs::set_permissions(&since, fs::Permissions::from_mode(0o600)).unwrap();
+
+        fs::write(&file, "newer").unwrap();
+        let updated = ConfigArchive::update_archive(&since, &options).unwrap();
+        let tar_path = Path::new("absolute").join(file.strip_prefix("/").unwrap());
+
+        assert_eq!(vec![tar_path], updated.diff.changed);
+        assert_eq!(1, updated.skipped.len());
+        assert_eq!(missing, updated.skipped[0].local);
+
+        // the updated archive keeps its base and permissions, and no temporary file is left
+        let incremental = ConfigArchive::retrieve_configs(&since)
+            .unwrap()
+            .incremental
+            .unwrap();
+        assert_eq!("base.tar", incremental.base);
+        assert_eq!(0o600, fs::metadata(&since).unwrap().mode() & 0o777);
+        assert_eq!(3, fs::read_dir(&dir).unwrap().count());
+
+        assert!(ConfigArchive::update_archive(&since, &ArchiveOptions { strict: true }).is_err());
+        assert_eq!(3, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Build a tar of files with the given paths and content, in order.
+    pub(super) fn tar_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+
+        for (path, content) in files {
+            let mut file = Header::new_gnu();
+            file.set_size(content.len() as u64);
+            file.set_mode(0o644);
+            builder
+                .append_data(&mut file, path, content.as_bytes())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_legacy_archive() {
+        let manifest =
+            "format_version = 1\n[incremental]\nbase = 'base.tar'\nremoved = ['etc/fstab']";
+        let legacy = tar_of(&[
+            (".rconf", manifest),
+            ("etc/hosts", "hosts"),
+            ("bin/foo", "foo"),
+        ]);
+        let mut cfg = ConfigArchive::with_reader(Cursor::new(legacy)).unwrap();
+
+        let mut visited = vec![];
+        cfg.for_each_entry(|path, _| {
+            visited.push(path.to_tar_path());
+            Ok(())
+        })
+        .unwrap();
+
+        // absolute paths were stored at the archive root before format version 2
+        assert_eq!(
+            vec![
+                Path::new("absolute/etc/hosts").to_path_buf(),
+                Path::new("bin/foo").to_path_buf()
+            ],
+            visited
+        );
+        assert_eq!(
+            vec!["absolute/etc/fstab".to_string()],
+            cfg.incremental.unwrap().removed
+        );
+    }
+
+    #[test]
+    fn test_install_unversioned_archive() {
+        let dir = std::env::temp_dir().join(format!("rconf-unversioned-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let unversioned = tar_of(&[
+            (".rconf", "[paths]\nhome = ['.bashrc']"),
+            ("home/.bashrc", "bashrc"),
+            ("bin/foo", "foo"),
+            ("data/fonts", "fonts"),
+        ]);
+        let options = InstallOptions {
+            prefix: Some(dir.join("root")),
+            home: Some(dir.join("home")),
+            skip_packages: true,
+            ..Default::default()
+        };
+
+        ConfigArchive::with_reader(Cursor::new(unversioned))
+            .unwrap()
+            .install_configs(&options, None)
+            .unwrap();
+
+        // only home and config paths were distinguished from absolute paths before versioning
+        assert_eq!(
+            "bashrc",
+            fs::read_to_string(dir.join("home/.bashrc")).unwrap()
+        );
+        assert_eq!("foo", fs::read_to_string(dir.join("root/bin/foo")).unwrap());
+        assert_eq!(
+            "fonts",
+            fs::read_to_string(dir.join("root/data/fonts")).unwrap()
+        );
+        assert!(!dir.join("home/.local/bin/foo").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_install_state() {
+        let dir = std::env::temp_dir().join(format!("rconf-install-state-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let install = |merge: bool| {
+            let archive = tar_of(&[
+                (".rconf", "format_version = 2\n[paths]\nhome = ['.bashrc']"),
+                ("home/.bashrc", "bashrc"),
+            ]);
+            let options = InstallOptions {
+                home: Some(dir.clone()),
+                skip_packages: true,
+                merge,
+                ..Default::default()
+            };
+
+            ConfigArchive::with_reader(Cursor::new(archive))
+                .unwrap()
+                .install_configs(&options, None)
+                .unwrap();
+        };
+        let recorded = dir.join(".local/share/rconf/installed");
+
+        // the installed content is only recorded as the base of a merge
+        install(false);
+        assert!(!recorded.exists());
+
+        install(true);
+        assert!(recorded
+            .join(dir.strip_prefix("/").unwrap())
+            .join(".bashrc")
+            .is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_install_stdin_prompt() {
+        let dir = std::env::temp_dir().join(format!("rconf-stdin-prompt-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let archive = tar_of(&[
+            (
+                ".rconf",
+                "format_version = 2\n[paths]\nhome = [{ path = '.bashrc', on_conflict = 'prompt' }]",
+            ),
+            ("home/.bashrc", "bashrc"),
+        ]);
+        let options = InstallOptions {
+            home: Some(dir.clone()),
+            skip_packages: true,
+            archive_from_stdin: true,
+            ..Default::default()
+        };
+
+        // the answers to a prompt would be read from the archive
+        assert!(ConfigArchive::with_reader(Cursor::new(archive))
+            .unwrap()
+            .install(&options)
+            .is_err());
+        assert!(!dir.join(".bashrc").exists());
+    }
+
+    #[test]
+    fn test_install_skeleton() {
+        let dir = std::env::temp_dir().join(format!("rconf-skeleton-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let archive = tar_of(&[
+            (".rconf", "format_version = 2\n[paths]\nhome = ['.bashrc']"),
+            ("home/.bashrc", "bashrc"),
+        ]);
+        let skel = dir.join("etc/skel");
+        let options = InstallOptions {
+            home: Some(skel.clone()),
+            skip_packages: true,
+            skeleton: true,
+            ..Default::default()
+        };
+
+        ConfigArchive::with_reader(Cursor::new(archive))
+            .unwrap()
+            .install_configs(&options, None)
+            .unwrap();
+
+        // the skeleton is copied to every new user, so no state is recorded in it
+        assert_eq!("bashrc", fs::read_to_string(skel.join(".bashrc")).unwrap());
+        assert!(!skel.join(".local").exists());
+
+        let merge = InstallOptions {
+            merge: true,
+            ..options
+        };
+        assert!(merge.state().is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_plain_archive() {
+        let mut builder = Builder::new(vec![]);
+
+        let mut dir = Header::new_gnu();
+        dir.set_entry_type(tar::EntryType::Directory);
+        dir.set_size(0);
+        dir.set_mode(0o755);
+        builder.append_data(&mut dir, ".", std::io::empty()).unwrap();
+
+        let mut file = Header::new_gnu();
+        file.set_size(5);
+        file.set_mode(0o644);
+        builder
+            .append_data(&mut file, "./etc/hosts", "hosts".as_bytes())
+            .unwrap();
+        builder
+            .append_data(&mut file, "absolute/etc/fstab", "fstab".as_bytes())
+            .unwrap();
+
+        let plain = builder.into_inner().unwrap();
+
+        match ConfigArchive::with_reader(Cursor::new(plain.clone())) {
+            Err(ConfigError::MissingManifest) => {}
+            _ => panic!("a plain archive should not be read without a manifest"),
+        }
+
+        let mut cfg = ConfigArchive::with_plain_reader(Cursor::new(plain)).unwrap();
+        assert!(cfg.paths.is_none());
+
+        let mut visited = vec![];
+        cfg.for_each_entry(|path, _| {
+            visited.push(path.to_tar_path());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                Path::new("absolute/etc/hosts").to_path_buf(),
+                Path::new("absolute/etc/fstab").to_path_buf()
+            ],
+            visited
+        );
     }
 }
@@ -0,0 +1,454 @@
+use super::conflict::{ConflictPolicies, Outcome};
+use super::error::Result;
+use super::manager::Invocation;
+use super::path::{ArchivePath, ArchivedEntry, Roots};
+use super::{
+    install_entry, remove_local, replace_file, ArchiveReader, ConfigArchive, Incremental,
+    InstallOptions,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tar::Entry;
+
+/// An archive layered on top of the archives before it.
+pub struct Layer {
+    /// The name used to refer to the layer in reports, typically the archive's path.
+    pub name: String,
+    pub cfg: ConfigArchive,
+}
+
+impl Layer {
+    /// Construct a new [Layer](struct.Layer.html) from a tar archive, see
+    /// [with_archive](../struct.ConfigArchive.html#method.with_archive).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// archive.
+    pub fn with_archive<P: AsRef<Path>>(path: P) -> Result<Layer> {
+        Ok(Layer {
+            name: path.as_ref().to_string_lossy().to_string(),
+            cfg: ConfigArchive::with_archive(path)?,
+        })
+    }
+
+    /// Construct a new [Layer](struct.Layer.html) from a tar archive read from a stream such as
+    /// stdin, see [with_reader](../struct.ConfigArchive.html#method.with_reader).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// archive's manifest.
+    pub fn with_reader<R: Read + 'static>(name: &str, reader: R) -> Result<Layer> {
+        Ok(Layer {
+            name: name.to_string(),
+            cfg: ConfigArchive::with_reader(reader)?,
+        })
+    }
+}
+
+/// An entry of a lower layer which was replaced by the same entry in a higher layer.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Overridden {
+    pub path: PathBuf,
+
+    /// The name of the layer whose entry was replaced.
+    pub layer: String,
+
+    /// The name of the layer whose entry replaced it.
+    pub by: String,
+}
+
+/// An entry installed from a layer of an [Overlay](struct.Overlay.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Installed {
+    pub path: PathBuf,
+    pub destination: PathBuf,
+
+    /// The name of the layer which supplied the entry.
+    pub layer: String,
+
+    /// Whether the entry was written or merged, or an existing local file was kept or backed up.
+    pub outcome: Outcome,
+}
+
+/// A local path removed by an incremental layer of an [Overlay](struct.Overlay.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Removed {
+    pub path: PathBuf,
+
+    /// The location which was removed, resolved with the roots installed to.
+    pub destination: PathBuf,
+}
+
+/// The outcome of installing an [Overlay](struct.Overlay.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstallReport {
+    pub installed: Vec<Installed>,
+    pub overridden: Vec<Overridden>,
+    pub invocations: Vec<Invocation>,
+
+    /// The paths removed by an incremental layer.
+    pub removed: Vec<Removed>,
+}
+
+/// The outcome of visiting the entries of an [Overlay](struct.Overlay.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverlayReport {
+    pub overridden: Vec<Overridden>,
+
+    /// The archive paths removed by an incremental layer which were not added back by a higher
+    /// layer.
+    pub removed: Vec<PathBuf>,
+}
+
+/// A stack of archives where the entries of later archives take priority over those of earlier
+/// archives.
+pub struct Overlay {
+    pub layers: Vec<Layer>,
+}
+
+impl Overlay {
+    /// Construct a new [Overlay](struct.Overlay.html) from archives in order of increasing
+    /// priority.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading an
+    /// archive.
+    pub fn with_archives<P: AsRef<Path>>(paths: &[P]) -> Result<Overlay> {
+        Ok(Overlay {
+            layers: paths
+                .iter()
+                .map(Layer::with_archive)
+                .collect::<Result<Vec<Layer>>>()?,
+        })
+    }
+
+    /// Combine the configurations of every layer. The paths and packages of every layer are
+    /// specified, while the package manager command and arguments of later layers take precedence.
+    pub fn config(&self) -> ConfigArchive {
+        let mut combined = ConfigArchive::new(None, None);
+
+        for layer in &self.layers {
+            combined.extend(&layer.cfg);
+        }
+
+        combined
+    }
+
+    /// Visit the entries of every layer such that each archive path is visited exactly once, from
+    /// the highest priority layer containing it. The visitor receives the index of the entry's
+    /// layer.
+    ///
+    /// Layers are read once from the highest to the lowest priority, so entries are not visited
+    /// in archive order.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
+    /// an archive, or any error returned by the visitor.
+    pub fn for_each_entry<F>(&mut self, mut visit: F) -> Result<OverlayReport>
+    where
+        F: FnMut(usize, &ArchivePath, &mut Entry<ArchiveReader>) -> Result<()>,
+    {
+        let mut report = OverlayReport::default();
+
+        // the layer from which each path was visited
+        let mut visited: HashMap<PathBuf, usize> = HashMap::new();
+        let names: Vec<String> = self.layers.iter().map(|layer| layer.name.clone()).collect();
+
+        for (index, layer) in self.layers.iter_mut().enumerate().rev() {
+            layer.cfg.for_each_entry(|path, entry| {
+                let tar_path = path.to_tar_path();
+
+                if let Some(by) = visited.get(&tar_path) {
+                    if !entry.header().entry_type().is_dir() {
+                        report.overridden.push(Overridden {
+                            path: tar_path,
+                            layer: names[index].clone(),
+                            by: names[*by].clone(),
+                        });
+                    }
+
+                    return Ok(());
+                }
+
+                // entries beneath a path removed by a higher layer are removed too
+                if report
+                    .removed
+                    .iter()
+                    .any(|removed| tar_path.starts_with(removed))
+                {
+                    return Ok(());
+                }
+
+                visit(index, path, entry)?;
+                visited.insert(tar_path, index);
+
+                Ok(())
+            })?;
+
+            // paths with entries from a higher layer are kept
+            if let Some(incremental) = &layer.cfg.incremental {
+                for removed in &incremental.removed {
+                    let removed = PathBuf::from(removed);
+
+                    if !visited.keys().any(|visited| visited.starts_with(&removed)) {
+                        report.removed.push(removed);
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Install the layers, writing every destination exactly once from the highest priority layer
+    /// containing it. The packages of every layer are installed with the package manager of the
+    /// highest priority layer specifying one.
+    ///
+    /// Conflicts with existing local files are resolved with the policies configured by the layer
+    /// supplying the entry, see [install_configs](../struct.ConfigArchive.html#method.install_configs).
+    /// The privileged steps are run together once everything else was installed.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error installing the
+    /// packages or the archived configurations.
+    pub fn install(&mut self, options: &InstallOptions) -> Result<InstallReport> {
+        // the policies are checked before anything is installed
+        let policies: Vec<ConflictPolicies> = self
+            .layers
+            .iter()
+            .map(|layer| options.policies(layer.cfg.paths.as_ref()))
+            .collect::<Result<_>>()?;
+
+        let mut escalation = options.escalation()?;
+        let mut invocations =
+            options.install_packages(self.config().manager.as_ref(), escalation.as_mut())?;
+
+        let names: Vec<String> = self.layers.iter().map(|layer| layer.name.clone()).collect();
+        let roots: Vec<Roots> = self
+            .layers
+            .iter()
+            .map(|layer| options.roots_for(&layer.cfg))
+            .collect::<Result<_>>()?;
+        let state = options.state()?;
+        let mut installed = vec![];
+
+        let report = self.for_each_entry(|index, path, entry| {
+            let tar_path = path.to_tar_path();
+            let policy = policies[index].for_path(&tar_path);
+            let (destination, outcome) = install_entry(
+                path,
+                &roots[index],
+                entry,
+                policy,
+                state.as_ref(),
+                options,
+                escalation.as_mut(),
+            )?;
+
+            installed.push(Installed {
+                path: tar_path,
+                destination,
+                layer: names[index].clone(),
+                outcome,
+            });
+
+            Ok(())
+        })?;
+
+        let roots = options.roots_for(&self.config())?;
+        let mut removed = vec![];
+
+        for tar_path in &report.removed {
+            if let Some(path) = ArchivePath::from_tar_path(tar_path) {
+                removed.push(Removed {
+                    path: tar_path.clone(),
+                    destination: remove_local(&path, &roots, escalation.as_mut())?,
+                });
+            }
+        }
+
+        if let Some(escalation) = &mut escalation {
+            invocations.extend(escalation.run()?);
+        }
+
+        Ok(InstallReport {
+            installed,
+            overridden: report.overridden,
+            invocations,
+            removed,
+        })
+    }
+
+    /// Merge every layer into a single archive, see [config](#method.config) and
+    /// [for_each_entry](#method.for_each_entry). When the lowest layer is an incremental archive
+    /// the merged archive is an incremental archive of the same base, removing every path removed
+    /// by any of the layers.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading from
+    /// an archive, or writing the merged archive.
+    pub fn write_merged(&mut self, path: &Path) -> Result<Vec<Overridden>> {
+        let mut cfg = self.config();
+
+        // removals are only kept when there is a base which they apply to
+        if let Some(base) = self
+            .layers
+            .first()
+            .and_then(|layer| layer.cfg.incremental.as_ref())
+        {
+            let mut removed: Vec<String> = vec![];
+
+            for layer in &self.layers {
+                if let Some(incremental) = &layer.cfg.incremental {
+                    for path in &incremental.removed {
+                        if !removed.contains(path) {
+                            removed.push(path.clone());
+                        }
+                    }
+                }
+            }
+
+            cfg.incremental = Some(Incremental {
+                base: base.base.clone(),
+                removed,
+            });
+        }
+
+        let mut report = OverlayReport::default();
+
+        // the merged archive may replace one of the layers, which are read while it is written
+        replace_file(path, |tmp| {
+            cfg.write_entries(File::create(tmp)?, |builder| {
+                let mut archived = vec![];
+
+                report = self.for_each_entry(|_, path, entry| {
+                    let tar_path = path.to_tar_path();
+                    let mut header = entry.header().clone();
+
+                    builder.append_data(&mut header, &tar_path, entry)?;
+                    archived.push(ArchivedEntry {
+                        tar_path,
+                        is_dir: header.entry_type().is_dir(),
+                    });
+
+                    Ok(())
+                })?;
+
+                Ok(archived)
+            })
+        })?;
+
+        Ok(report.overridden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Overlay;
+    use crate::configs::index::ArchiveIndex;
+    use crate::configs::path::PathSpecifier;
+    use crate::configs::tests::tar_of;
+    use crate::configs::{ArchiveOptions, ConfigArchive, InstallOptions};
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_install_layers() {
+        let dir = std::env::temp_dir().join(format!("rconf-install-layers-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // `true` accepts any arguments, so the combined packages can be installed with it
+        let lower = dir.join("lower.tar");
+        let higher = dir.join("higher.tar");
+        fs::write(
+            &lower,
+            tar_of(&[
+                (
+                    ".rconf",
+                    "format_version = 2\n[paths]\nhome = ['.lower', '.both']\n[manager]\nname = 'true'\npackages = ['git']",
+                ),
+                ("home/.lower", "lower"),
+                ("home/.both", "lower"),
+            ]),
+        )
+        .unwrap();
+        fs::write(
+            &higher,
+            tar_of(&[
+                (
+                    ".rconf",
+                    "format_version = 2\n[paths]\nhome = ['.both']\n[manager]\npackages = ['vim']",
+                ),
+                ("home/.both", "higher"),
+            ]),
+        )
+        .unwrap();
+
+        let home = dir.join("home");
+        let options = InstallOptions {
+            home: Some(home.clone()),
+            ..Default::default()
+        };
+        let report = Overlay::with_archives(&[&lower, &higher])
+            .unwrap()
+            .install(&options)
+            .unwrap();
+
+        // the higher layer wins where both have the path
+        assert_eq!("lower", fs::read_to_string(home.join(".lower")).unwrap());
+        assert_eq!("higher", fs::read_to_string(home.join(".both")).unwrap());
+        assert_eq!(2, report.installed.len());
+        assert_eq!(1, report.overridden.len());
+        assert_eq!(PathBuf::from("home/.both"), report.overridden[0].path);
+        assert_eq!(lower.to_string_lossy(), report.overridden[0].layer);
+        assert_eq!(higher.to_string_lossy(), report.overridden[0].by);
+
+        // the packages of every layer are installed with the only manager command
+        assert_eq!(1, report.invocations.len());
+        assert_eq!("true", report.invocations[0].command);
+        assert_eq!(vec!["git", "vim"], report.invocations[0].args);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_into_layer() {
+        let dir =
+            std::env::temp_dir().join(format!("rconf-merge-into-layer-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let archive = |name: &str| {
+            let file = dir.join(name);
+            fs::write(&file, name).unwrap();
+
+            let paths = PathSpecifier {
+                absolute: Some(vec![file.to_string_lossy().to_string().into()]),
+                ..Default::default()
+            };
+            let tar = dir.join(format!("{}.tar", name));
+            ConfigArchive::new(Some(paths), None)
+                .write_archive(&tar, &ArchiveOptions::default())
+                .unwrap();
+
+            tar
+        };
+
+        let (x, y) = (archive("x"), archive("y"));
+
+        let overridden = Overlay::with_archives(&[&x, &y])
+            .unwrap()
+            .write_merged(&x)
+            .unwrap();
+        assert!(overridden.is_empty());
+
+        // the entries of both layers were merged, without leaving the temporary file behind
+        assert_eq!(2, ArchiveIndex::with_archive(&x).unwrap().digests.len());
+        assert_eq!(4, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
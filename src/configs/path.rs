@@ -1,26 +1,34 @@
+use self::super::conflict::ConflictPolicy;
 use self::super::error::ConfigError;
-use std::fs::File;
+use self::super::{INSTALL_SCRIPT, MANIFEST, ROOTS, UNINSTALL_SCRIPT};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
 use std::path::{self, Path, PathBuf};
 use tar::Builder;
 
 macro_rules! archive_path_vec {
     ($property:expr, $kind:expr) => {{
         match $property {
-            None => vec![],
+            None => Ok(vec![]),
             Some(v) => v
                 .iter()
-                .map(|path| -> ArchivePath {
-                    ArchivePath {
+                .map(|entry| -> Result<ArchivePath, ConfigError> {
+                    Ok(ArchivePath {
                         kind: $kind,
-                        path: Path::new(path),
-                    }
+                        path: expand_path(&entry.path)?,
+                    })
                 })
                 .collect(),
         }
     }};
 }
 
-#[macro_export]
 macro_rules! try_dir {
     ($dir_fn:expr, $kind:expr) => {
         match $dir_fn() {
@@ -30,59 +38,398 @@ macro_rules! try_dir {
                     PathKind::ABSOLUTE => "absolute".to_string(),
                     PathKind::HOME => "Home".to_string(),
                     PathKind::CONFIG => "Config".to_string(),
+                    PathKind::DATA => "Data".to_string(),
+                    PathKind::STATE => "State".to_string(),
+                    PathKind::CACHE => "Cache".to_string(),
+                    PathKind::BIN => "Executable".to_string(),
+                    PathKind::ROOT(name) => format!("root '{}'", name),
                 }));
             }
         }
     };
 }
 
+/// Retrieve the value of an environment variable used by a configured path, the default is used
+/// when the variable is unset or empty.
+fn lookup_var(name: &str, default: Option<&str>, entry: &str) -> Result<String, ConfigError> {
+    match (env::var(name), default) {
+        (Ok(value), Some(default)) if value.is_empty() => Ok(default.to_string()),
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(default)) => Ok(default.to_string()),
+        (Err(_), None) => Err(ConfigError::UnsetVariable {
+            variable: name.to_string(),
+            entry: entry.to_string(),
+        }),
+    }
+}
+
+/// Expand a leading `~` to the home directory and the environment variables `$VAR`, `${VAR}`
+/// and `${VAR:-default}` in a configured path, and remove duplicate and trailing separators.
+///
+/// # Errors
+/// A [ConfigError](../error/enum.ConfigError.html) is returned if a variable without a default
+/// is unset, or the home directory could not be determined.
+pub(crate) fn expand_path(entry: &str) -> Result<Cow<'_, Path>, ConfigError> {
+    let is_tilde = entry == "~" || entry.starts_with("~/");
+    let is_normal = !entry.contains("//") && (entry.len() < 2 || !entry.ends_with('/'));
+
+    if !is_tilde && !entry.contains('$') && is_normal {
+        return Ok(Cow::Borrowed(Path::new(entry)));
+    }
+
+    let mut expanded = String::new();
+    let mut rest = entry;
+
+    if is_tilde {
+        expanded.push_str(&try_dir!(dirs::home_dir, PathKind::HOME).to_string_lossy());
+        rest = &rest[1..];
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        if let Some(braced) = rest.strip_prefix('{') {
+            let end = match braced.find('}') {
+                Some(end) => end,
+                None => {
+                    return Err(ConfigError::Argument(format!(
+                        "Unterminated variable in path '{}'",
+                        entry
+                    )))
+                }
+            };
+
+            let (name, default) = match braced[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&braced[..end], None),
+            };
+
+            expanded.push_str(&lookup_var(name, default, entry)?);
+            rest = &braced[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+
+            if end == 0 {
+                // a '$' which does not start a variable name is kept
+                expanded.push('$');
+            } else {
+                expanded.push_str(&lookup_var(&rest[..end], None, entry)?);
+            }
+
+            rest = &rest[end..];
+        }
+    }
+
+    expanded.push_str(rest);
+
+    // collecting the components removes duplicate and trailing separators
+    Ok(Cow::Owned(Path::new(&expanded).components().collect()))
+}
+
+/// An entry appended to an archive by [AppendSpecifier](trait.AppendSpecifier.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchivedEntry {
+    /// The relative path of the entry inside the archive.
+    pub tar_path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// A file or directory on the local system which is to be archived.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalEntry {
+    /// The relative path of the entry inside the archive.
+    pub tar_path: PathBuf,
+    pub local: PathBuf,
+    pub is_dir: bool,
+}
+
+/// Why a path was skipped when collecting the local entries.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkipReason {
+    /// The path does not exist, or is a symlink to nothing.
+    Missing,
+
+    /// The path is neither a file nor a directory, such as a socket, fifo, or device.
+    Special,
+
+    /// The path is a symlink to a directory containing it, which would be collected forever.
+    Loop,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SkipReason::Missing => write!(f, "does not exist"),
+            SkipReason::Special => write!(f, "is neither a file nor a directory"),
+            SkipReason::Loop => write!(f, "is a symlink to a directory containing it"),
+        }
+    }
+}
+
+/// A path which was skipped when collecting the local entries of a configured path.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SkippedPath {
+    /// The path as it is configured, which is or contains the skipped path.
+    pub entry: String,
+
+    /// The relative path the skipped path would have inside the archive.
+    pub tar_path: PathBuf,
+    pub local: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// The local files and directories collected from a [PathSpecifier](struct.PathSpecifier.html),
+/// along with the paths which were skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LocalEntries {
+    pub entries: Vec<LocalEntry>,
+
+    /// The skipped paths, except configured paths which are optional and do not exist.
+    pub skipped: Vec<SkippedPath>,
+}
+
+impl LocalEntries {
+    /// Record a skipped path, or fail if skipping is not allowed.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned when strict.
+    fn skip(&mut self, skipped: SkippedPath, strict: bool) -> Result<(), ConfigError> {
+        if !strict {
+            self.skipped.push(skipped);
+            return Ok(());
+        }
+
+        Err(match skipped.reason {
+            SkipReason::Missing => ConfigError::MissingPath {
+                entry: skipped.entry,
+                local: skipped.local,
+            },
+            reason => ConfigError::SkippedPath {
+                local: skipped.local,
+                reason: reason.to_string(),
+            },
+        })
+    }
+}
+
 /// Custom trait allowing for appending a [PathSpecifier](struct.PathSpecifier.html) to the type.
 pub trait AppendSpecifier {
-    fn append_path_specifier(&mut self, specifier: &PathSpecifier) -> Result<(), ConfigError>;
+    fn append_path_specifier(
+        &mut self,
+        specifier: &PathSpecifier,
+        roots: &Roots,
+    ) -> Result<Vec<ArchivedEntry>, ConfigError>;
+
+    fn append_local_entries(
+        &mut self,
+        entries: &[LocalEntry],
+    ) -> Result<Vec<ArchivedEntry>, ConfigError>;
+}
+
+/// Recursively collect a local file or directory and its contents. Directory contents are
+/// collected in a sorted order so that the resulting archive is reproducible.
+///
+/// Symlinks are followed so that the content they point to is archived, except those to a
+/// directory which is one of the given ancestors (by device and inode) as they would be followed
+/// forever. They are skipped along with special files and symlinks to nothing.
+fn collect_recursive(
+    entry: &str,
+    tar_path: PathBuf,
+    local: PathBuf,
+    ancestors: &mut Vec<(u64, u64)>,
+    strict: bool,
+    collected: &mut LocalEntries,
+) -> Result<(), ConfigError> {
+    let skip = |collected: &mut LocalEntries, tar_path, local, reason| {
+        let skipped = SkippedPath {
+            entry: entry.to_string(),
+            tar_path,
+            local,
+            reason,
+        };
+
+        collected.skip(skipped, strict)
+    };
+
+    let meta = match fs::metadata(&local) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return skip(collected, tar_path, local, SkipReason::Missing)
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    if meta.is_file() {
+        collected.entries.push(LocalEntry {
+            tar_path,
+            local,
+            is_dir: false,
+        });
+    } else if meta.is_dir() {
+        let id = (meta.dev(), meta.ino());
+
+        if ancestors.contains(&id) {
+            return skip(collected, tar_path, local, SkipReason::Loop);
+        }
+
+        let mut children = fs::read_dir(&local)?
+            .map(|child| child.map(|child| child.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        children.sort();
+
+        collected.entries.push(LocalEntry {
+            tar_path: tar_path.clone(),
+            local: local.clone(),
+            is_dir: true,
+        });
+
+        ancestors.push(id);
+        for child in children {
+            collect_recursive(
+                entry,
+                tar_path.join(&child),
+                local.join(&child),
+                ancestors,
+                strict,
+                collected,
+            )?;
+        }
+        ancestors.pop();
+    } else {
+        return skip(collected, tar_path, local, SkipReason::Special);
+    }
+
+    Ok(())
 }
 
 /// Extension for [Builder](../../../tar/builder/struct.Builder.html) allowing for adding all paths
 /// in a [PathSpecifier](struct.PathSpecifier.html) to be appended.
 ///
 /// This implementation handles appending both files and directories.
-impl AppendSpecifier for Builder<File> {
+impl<W: Write> AppendSpecifier for Builder<W> {
     /// Append the configuration files specified by the [PathSpecifier](struct.PathSpecifier.html)
+    /// and return every entry which was added to the archive.
     ///
-    /// All absolute paths are stored with their root at the archive root (ex /etc/gitconfig =>
-    /// archive.tar/etc/gitconfig). System dependent config locations will be stored in a
+    /// All absolute paths are stored with their root at the archive's absolute directory (ex
+    /// /etc/gitconfig => archive.tar/absolute/etc/gitconfig). System dependent config locations will be stored in a
     /// representative top level directory in the archive (ex $HOME/.basrhc => archive.tar/home).
     ///
     /// # Errors
     /// A [ConfigError](../error/enum.ConfigError.html) is returned on an error adding a file from
     /// the specifier into the builder.
-    fn append_path_specifier(&mut self, specifier: &PathSpecifier) -> Result<(), ConfigError> {
-        // retrieve vector of archive paths for all config paths
-        let mut all_paths = specifier.get_archiveable_paths(PathKind::ABSOLUTE);
-        all_paths.append(&mut specifier.get_archiveable_paths(PathKind::HOME));
-        all_paths.append(&mut specifier.get_archiveable_paths(PathKind::CONFIG));
-
-        // add all paths to the archive builder
-        for path in all_paths {
-            let path_buf: PathBuf = path.to_local_path()?;
-
-            if path_buf.is_file() {
-                self.append_path_with_name(path_buf, path.to_tar_path())?
-            } else if path_buf.is_dir() {
-                self.append_dir_all(path.to_tar_path(), path_buf)?
+    fn append_path_specifier(
+        &mut self,
+        specifier: &PathSpecifier,
+        roots: &Roots,
+    ) -> Result<Vec<ArchivedEntry>, ConfigError> {
+        self.append_local_entries(&specifier.local_entries(roots, false)?.entries)
+    }
+
+    /// Append previously collected local files and directories, see
+    /// [local_entries](struct.PathSpecifier.html#method.local_entries).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned on an error adding a file into
+    /// the builder.
+    fn append_local_entries(
+        &mut self,
+        entries: &[LocalEntry],
+    ) -> Result<Vec<ArchivedEntry>, ConfigError> {
+        let mut archived = vec![];
+
+        for entry in entries {
+            if entry.is_dir {
+                self.append_dir(&entry.tar_path, &entry.local)?;
+            } else {
+                self.append_path_with_name(&entry.local, &entry.tar_path)?;
             }
+
+            archived.push(ArchivedEntry {
+                tar_path: entry.tar_path.clone(),
+                is_dir: entry.is_dir,
+            });
         }
 
-        Ok(())
+        Ok(archived)
     }
 }
 
 /// Used to specify the type of path when retrieving the vectors from
 /// [ConfigPathSpecifier](struct.ConfigPathSpecifier.html).
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PathKind {
     ABSOLUTE,
     HOME,
     CONFIG,
+    DATA,
+    STATE,
+    CACHE,
+    BIN,
+
+    /// Paths relative to a named root defined by the configuration's `roots` table, see
+    /// [Roots](struct.Roots.html).
+    ROOT(String),
+}
+
+impl PathKind {
+    /// Every built in kind of path, in the order they are archived.
+    pub const ALL: [PathKind; 7] = [
+        PathKind::ABSOLUTE,
+        PathKind::HOME,
+        PathKind::CONFIG,
+        PathKind::DATA,
+        PathKind::STATE,
+        PathKind::CACHE,
+        PathKind::BIN,
+    ];
+
+    /// The directory in the archive storing paths of this kind, the paths of a root are stored
+    /// beneath `roots/<name>`.
+    pub fn prefix(&self) -> PathBuf {
+        let prefix = match self {
+            PathKind::ABSOLUTE => "absolute",
+            PathKind::HOME => "home",
+            PathKind::CONFIG => "config",
+            PathKind::DATA => "data",
+            PathKind::STATE => "state",
+            PathKind::CACHE => "cache",
+            PathKind::BIN => "bin",
+            PathKind::ROOT(name) => return Path::new(ROOTS).join(name),
+        };
+
+        PathBuf::from(prefix)
+    }
+
+    /// Determine the kind of a path inside an archive from its top level directories, or none if
+    /// it is not beneath the directory of any kind.
+    fn of_tar_path(path: &Path) -> Option<PathKind> {
+        if path.starts_with(ROOTS) {
+            if let Some(name) = path.iter().nth(1) {
+                return Some(PathKind::ROOT(name.to_string_lossy().to_string()));
+            }
+        }
+
+        PathKind::ALL
+            .iter()
+            .find(|kind| path.starts_with(kind.prefix()))
+            .cloned()
+    }
+
+    /// Determine the kind of a path inside an archive written before absolute paths were stored
+    /// beneath their own directory, where every path outside the directories of the other kinds
+    /// is absolute.
+    fn of_legacy_tar_path(path: &Path) -> PathKind {
+        match PathKind::of_tar_path(path) {
+            Some(PathKind::ABSOLUTE) | None => PathKind::ABSOLUTE,
+            Some(kind) => kind,
+        }
+    }
 }
 
 impl<P> From<P> for PathKind
@@ -95,124 +442,567 @@ where
 
         if path.is_absolute() {
             PathKind::ABSOLUTE
-        } else if path.starts_with("home") {
-            PathKind::HOME
-        } else if path.starts_with("config") {
-            PathKind::CONFIG
         } else {
-            PathKind::ABSOLUTE
+            PathKind::of_legacy_tar_path(path)
         }
     }
 }
 
 /// Intermediate type for adding the paths of a
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ArchivePath<'a> {
     pub kind: PathKind,
-    pub path: &'a Path,
+    pub path: Cow<'a, Path>,
 }
 
 impl<'a> ArchivePath<'a> {
     /// Construct an [ArchivePath](struct.ArchivePath.html) from a file's relative path inside a
     /// config archive.
+    ///
+    /// The archive manifest and generated scripts are not configuration files and will never be
+    /// converted.
     pub fn from_tar_path(path: &'a Path) -> Option<ArchivePath<'a>> {
-        if path.to_str().unwrap() == ".rconf" {
-            None
-        } else if path.starts_with("home") {
-            Some(ArchivePath {
-                kind: PathKind::HOME,
-                path: path.strip_prefix("home").unwrap(),
-            })
-        } else if path.starts_with("config") {
-            Some(ArchivePath {
-                kind: PathKind::CONFIG,
-                path: path.strip_prefix("config").unwrap(),
-            })
-        } else if path.is_relative() {
-            // absolute paths are stored in a relative path of the same name without the leading '/'
-            Some(ArchivePath {
-                kind: PathKind::ABSOLUTE,
-                path,
-            })
-        } else {
-            None
+        if ArchivePath::is_reserved(path) {
+            return None;
         }
+
+        let kind = PathKind::of_tar_path(path)?;
+        let path = path.strip_prefix(kind.prefix()).unwrap().into();
+
+        Some(ArchivePath { kind, path })
+    }
+
+    /// Construct an [ArchivePath](struct.ArchivePath.html) from a file's relative path inside a
+    /// config archive written before format version 2, which stored absolute paths at the archive
+    /// root without their leading '/' (ex archive.tar/etc/gitconfig => /etc/gitconfig).
+    pub fn from_legacy_tar_path(path: &'a Path) -> Option<ArchivePath<'a>> {
+        ArchivePath::from_root_tar_path(path, PathKind::of_legacy_tar_path(path))
+    }
+
+    /// Construct an [ArchivePath](struct.ArchivePath.html) from a file's relative path inside a
+    /// config archive written before the format was versioned, which only distinguished home and
+    /// config paths from the absolute paths stored at the archive root (ex archive.tar/bin/foo =>
+    /// /bin/foo).
+    pub fn from_unversioned_tar_path(path: &'a Path) -> Option<ArchivePath<'a>> {
+        let kind = match PathKind::of_tar_path(path) {
+            Some(kind @ PathKind::HOME) | Some(kind @ PathKind::CONFIG) => kind,
+            _ => PathKind::ABSOLUTE,
+        };
+
+        ArchivePath::from_root_tar_path(path, kind)
+    }
+
+    /// Construct an [ArchivePath](struct.ArchivePath.html) of the given kind from a file's relative
+    /// path inside an archive storing absolute paths at the archive root.
+    fn from_root_tar_path(path: &'a Path, kind: PathKind) -> Option<ArchivePath<'a>> {
+        if ArchivePath::is_reserved(path) {
+            return None;
+        }
+
+        let path = match kind {
+            PathKind::ABSOLUTE => path.into(),
+            _ => path.strip_prefix(kind.prefix()).unwrap().into(),
+        };
+
+        Some(ArchivePath { kind, path })
+    }
+
+    /// Whether a path inside an archive is never a configuration file, such as the manifest.
+    fn is_reserved(path: &Path) -> bool {
+        path.is_absolute()
+            || [MANIFEST, INSTALL_SCRIPT, UNINSTALL_SCRIPT]
+                .iter()
+                .any(|reserved| path == Path::new(reserved))
     }
 
     /// Retrieve the relative path for a config file inside an archive.
     pub fn to_tar_path(&self) -> PathBuf {
         let mut path = PathBuf::new();
 
-        path.push(match self.kind {
-            PathKind::ABSOLUTE => "",
-            PathKind::HOME => "home",
-            PathKind::CONFIG => "config",
-        });
+        path.push(self.kind.prefix());
 
-        path.push(match self.kind {
-            PathKind::ABSOLUTE => match self.path.strip_prefix("/") {
-                Ok(p) => return p.to_path_buf(),
-                Err(_) => return self.path.to_path_buf(),
-            },
-            _ => self.path,
-        });
+        // a leading separator would replace the prefix rather than be joined to it
+        path.push(self.path.strip_prefix("/").unwrap_or(&self.path));
 
         path
     }
 
     /// Retrieve the path on the local system corresponding to the
-    /// [ArchivePath](struct.ArchivePath.html).
+    /// [ArchivePath](struct.ArchivePath.html). Paths of a root can not be resolved without the
+    /// root's directory, see [to_local_path_in](#method.to_local_path_in).
     ///
     /// # Errors
     /// A [ConfigError](../error/enum.ConfigError.html) on an error determining a system directory
     /// such as the home or config directories.
     pub fn to_local_path(&self) -> Result<PathBuf, ConfigError> {
-        let mut buf = PathBuf::new();
+        self.to_local_path_in(&Roots::default())
+    }
 
-        match &self.kind {
-            PathKind::ABSOLUTE => buf.push(path::MAIN_SEPARATOR.to_string()),
-            PathKind::HOME => buf.push(try_dir!(dirs::home_dir, PathKind::HOME)),
-            PathKind::CONFIG => buf.push(try_dir!(dirs::config_dir, PathKind::CONFIG)),
-        };
+    /// Retrieve the path on the local system corresponding to the
+    /// [ArchivePath](struct.ArchivePath.html), resolving the paths of a root with the given roots.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) on an error determining a system directory
+    /// such as the home or config directories, or if the path's root is not given.
+    pub fn to_local_path_in(&self, roots: &Roots) -> Result<PathBuf, ConfigError> {
+        let mut buf = roots.dir_of(&self.kind)?;
 
-        buf.push(self.path);
+        // a leading separator would replace the directory rather than be joined to it
+        buf.push(self.path.strip_prefix("/").unwrap_or(&self.path));
 
         Ok(buf)
     }
 }
 
+/// The local directories of the named roots which paths may be specified relative to, along with
+/// an alternate root and home directory which every local path is redirected beneath.
+///
+/// Roots are defined by the `roots` table of the configuration, ex
+/// `roots.workspace = "$WORKSPACE"` allows for `paths.workspace = [".editorconfig"]`, and may be
+/// given another directory when installing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Roots {
+    pub dirs: BTreeMap<String, PathBuf>,
+
+    /// The directory which every local path except those of the home below is placed beneath,
+    /// ex a prefix of `/mnt` resolves `/etc/hosts` to `/mnt/etc/hosts`.
+    pub prefix: Option<PathBuf>,
+
+    /// The home directory replacing the user's, the paths of the user's directories such as
+    /// their config directory are resolved at their default locations beneath it.
+    pub home: Option<PathBuf>,
+}
+
+impl Roots {
+    /// Resolve the configured roots by expanding their variables (see
+    /// [expand_path](fn.expand_path.html)), the given directories take precedence over those
+    /// configured.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned if a root's name cannot be
+    /// used (see [check_name](#method.check_name)), or a configured root which is not overridden
+    /// uses an unset variable.
+    pub fn new(
+        configured: &BTreeMap<String, String>,
+        overrides: &BTreeMap<String, PathBuf>,
+    ) -> Result<Roots, ConfigError> {
+        let mut dirs = overrides.clone();
+
+        for name in configured.keys().chain(overrides.keys()) {
+            Roots::check_name(name)?;
+        }
+
+        for (name, dir) in configured {
+            if !dirs.contains_key(name) {
+                dirs.insert(name.clone(), expand_path(dir)?.into_owned());
+            }
+        }
+
+        Ok(Roots {
+            dirs,
+            ..Roots::default()
+        })
+    }
+
+    /// Check that a name can be used for a root, as its paths are specified by a key of the
+    /// `paths` table beside those of the built in kinds, and archived beneath `roots/<name>`.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned if the name is that of a built
+    /// in kind of path, or is not a single directory name, such as one containing a separator.
+    pub fn check_name(name: &str) -> Result<(), ConfigError> {
+        let is_kind = PathKind::ALL
+            .iter()
+            .any(|kind| kind.prefix() == Path::new(name));
+        let is_dir_name = !name.is_empty() && name != "." && name != ".." && !name.contains('/');
+
+        if is_kind || !is_dir_name {
+            return Err(ConfigError::InvalidRoot(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Retrieve the local directory which paths of the given kind are relative to.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned on an error determining a
+    /// system directory, or if the root is not defined.
+    pub fn dir_of(&self, kind: &PathKind) -> Result<PathBuf, ConfigError> {
+        let user_dir = self.home.as_ref().and_then(|home| match kind {
+            PathKind::HOME => Some(home.clone()),
+            PathKind::CONFIG => Some(home.join(".config")),
+            PathKind::DATA => Some(home.join(".local/share")),
+            PathKind::STATE => Some(home.join(".local/state")),
+            PathKind::CACHE => Some(home.join(".cache")),
+            PathKind::BIN => Some(home.join(".local/bin")),
+            _ => None,
+        });
+
+        if let Some(dir) = user_dir {
+            return Ok(dir);
+        }
+
+        let dir = match kind {
+            PathKind::ABSOLUTE => PathBuf::from(path::MAIN_SEPARATOR.to_string()),
+            PathKind::HOME => try_dir!(dirs::home_dir, PathKind::HOME),
+            PathKind::CONFIG => try_dir!(dirs::config_dir, PathKind::CONFIG),
+            PathKind::DATA => try_dir!(dirs::data_dir, PathKind::DATA),
+            PathKind::STATE => try_dir!(dirs::state_dir, PathKind::STATE),
+            PathKind::CACHE => try_dir!(dirs::cache_dir, PathKind::CACHE),
+            PathKind::BIN => try_dir!(dirs::executable_dir, PathKind::BIN),
+            PathKind::ROOT(name) => self.dir(name)?.to_path_buf(),
+        };
+
+        match &self.prefix {
+            Some(prefix) => Ok(prefix.join(dir.strip_prefix("/").unwrap_or(&dir))),
+            None => Ok(dir),
+        }
+    }
+
+    /// Retrieve the directory of a root.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned if the root is not defined.
+    pub fn dir(&self, name: &str) -> Result<&Path, ConfigError> {
+        match self.dirs.get(name) {
+            Some(dir) => Ok(dir),
+            None => Err(ConfigError::UnknownRoot(name.to_string())),
+        }
+    }
+}
+
+/// A configured path along with the options applying to it and everything beneath it.
+///
+/// In the configuration an entry is either the path alone or a table of the path and its options,
+/// ex `paths.home = [".profile", { path = ".bashrc", on_conflict = "backup" }]`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(from = "PathEntryRepr", into = "PathEntryRepr")]
+pub struct PathEntry {
+    pub path: String,
+
+    /// The policy for replacing existing local files when installing, see
+    /// [ConflictPolicy](../conflict/enum.ConflictPolicy.html).
+    pub on_conflict: Option<ConflictPolicy>,
+
+    /// Whether the path may not exist when archiving, in which case it is skipped quietly.
+    pub optional: bool,
+}
+
+impl PathEntry {
+    /// Determine whether any options are specified for the path.
+    fn has_options(&self) -> bool {
+        self.on_conflict.is_some() || self.optional
+    }
+}
+
+impl From<String> for PathEntry {
+    fn from(path: String) -> PathEntry {
+        PathEntry {
+            path,
+            on_conflict: None,
+            optional: false,
+        }
+    }
+}
+
+impl From<&str> for PathEntry {
+    fn from(path: &str) -> PathEntry {
+        PathEntry::from(path.to_string())
+    }
+}
+
+/// The serialized form of a [PathEntry](struct.PathEntry.html), written as the path alone when
+/// there are no options.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum PathEntryRepr {
+    Path(String),
+    Table {
+        path: String,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on_conflict: Option<ConflictPolicy>,
+
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        optional: bool,
+    },
+}
+
+impl From<PathEntryRepr> for PathEntry {
+    fn from(repr: PathEntryRepr) -> PathEntry {
+        match repr {
+            PathEntryRepr::Path(path) => PathEntry::from(path),
+            PathEntryRepr::Table {
+                path,
+                on_conflict,
+                optional,
+            } => PathEntry {
+                path,
+                on_conflict,
+                optional,
+            },
+        }
+    }
+}
+
+impl From<PathEntry> for PathEntryRepr {
+    fn from(entry: PathEntry) -> PathEntryRepr {
+        if entry.has_options() {
+            PathEntryRepr::Table {
+                path: entry.path,
+                on_conflict: entry.on_conflict,
+                optional: entry.optional,
+            }
+        } else {
+            PathEntryRepr::Path(entry.path)
+        }
+    }
+}
+
+/// Deserialize a table keyed by the names of roots, such as the `roots` table, rejecting any name
+/// which cannot be used for a root, see [check_name](struct.Roots.html#method.check_name).
+pub(crate) fn deserialize_roots<'de, D, T>(deserializer: D) -> Result<BTreeMap<String, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let roots = BTreeMap::<String, T>::deserialize(deserializer)?;
+
+    for name in roots.keys() {
+        Roots::check_name(name).map_err(D::Error::custom)?;
+    }
+
+    Ok(roots)
+}
+
 /// Container for all configuration files specified in the configuration.
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Default, Deserialize, Serialize)]
 #[serde(rename(deserialize = ""))]
 pub struct PathSpecifier {
-    pub absolute: Option<Vec<String>>,
-    pub home: Option<Vec<String>>,
-    pub config: Option<Vec<String>>,
+    pub absolute: Option<Vec<PathEntry>>,
+    pub home: Option<Vec<PathEntry>>,
+    pub config: Option<Vec<PathEntry>>,
+
+    /// Paths relative to the user's data directory (typically `.local/share`).
+    pub data: Option<Vec<PathEntry>>,
+
+    /// Paths relative to the user's state directory (typically `.local/state`).
+    pub state: Option<Vec<PathEntry>>,
+
+    /// Paths relative to the user's cache directory (typically `.cache`).
+    pub cache: Option<Vec<PathEntry>>,
+
+    /// Paths relative to the user's executable directory (typically `.local/bin`).
+    pub bin: Option<Vec<PathEntry>>,
+
+    /// Paths relative to a named root, keyed by the root's name (ex `paths.workspace`). Any key
+    /// other than those of the built in kinds is the name of a root, which must be a single
+    /// directory name, see [check_name](struct.Roots.html#method.check_name).
+    #[serde(flatten, deserialize_with = "deserialize_roots")]
+    pub roots: BTreeMap<String, Vec<PathEntry>>,
 }
 
 impl PathSpecifier {
     /// Retrieve a vector of paths as [ArchivePath](struct.ArchivePath.html) which can be easier
-    /// stored in an archive.
-    fn get_archiveable_paths(&self, kind: PathKind) -> Vec<ArchivePath> {
+    /// stored in an archive, with their variables expanded.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned if a path uses an unset
+    /// variable, see [expand_path](fn.expand_path.html).
+    fn get_archiveable_paths(&self, kind: &PathKind) -> Result<Vec<ArchivePath<'_>>, ConfigError> {
         match kind {
             PathKind::ABSOLUTE => archive_path_vec!(&self.absolute, PathKind::ABSOLUTE),
             PathKind::HOME => archive_path_vec!(&self.home, PathKind::HOME),
             PathKind::CONFIG => archive_path_vec!(&self.config, PathKind::CONFIG),
+            PathKind::DATA => archive_path_vec!(&self.data, PathKind::DATA),
+            PathKind::STATE => archive_path_vec!(&self.state, PathKind::STATE),
+            PathKind::CACHE => archive_path_vec!(&self.cache, PathKind::CACHE),
+            PathKind::BIN => archive_path_vec!(&self.bin, PathKind::BIN),
+            PathKind::ROOT(name) => archive_path_vec!(self.roots.get(name), kind.clone()),
+        }
+    }
+
+    /// Retrieve the configured entries of the given kind.
+    fn entries_of(&self, kind: &PathKind) -> &[PathEntry] {
+        let entries = match kind {
+            PathKind::ABSOLUTE => &self.absolute,
+            PathKind::HOME => &self.home,
+            PathKind::CONFIG => &self.config,
+            PathKind::DATA => &self.data,
+            PathKind::STATE => &self.state,
+            PathKind::CACHE => &self.cache,
+            PathKind::BIN => &self.bin,
+            PathKind::ROOT(name) => return self.roots.get(name).map_or(&[], Vec::as_slice),
+        };
+
+        entries.as_deref().unwrap_or_default()
+    }
+
+    /// Retrieve every kind of path which may be specified, the built in kinds followed by the
+    /// specified roots.
+    fn kinds(&self) -> Vec<PathKind> {
+        PathKind::ALL
+            .iter()
+            .cloned()
+            .chain(self.roots.keys().map(|name| PathKind::ROOT(name.clone())))
+            .collect()
+    }
+
+    /// Retrieve every configured entry along with its [ArchivePath](struct.ArchivePath.html).
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned if a path uses an unset
+    /// variable.
+    pub fn path_entries(&self) -> Result<Vec<(ArchivePath<'_>, &PathEntry)>, ConfigError> {
+        let mut entries = vec![];
+
+        for kind in self.kinds() {
+            let paths = self.get_archiveable_paths(&kind)?;
+            entries.extend(paths.into_iter().zip(self.entries_of(&kind)));
+        }
+
+        Ok(entries)
+    }
+
+    /// Retrieve the [ArchivePath](struct.ArchivePath.html) of every specified path regardless of
+    /// its kind.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned if a path uses an unset
+    /// variable.
+    pub fn archive_paths(&self) -> Result<Vec<ArchivePath<'_>>, ConfigError> {
+        let mut all_paths = vec![];
+
+        for kind in self.kinds() {
+            all_paths.append(&mut self.get_archiveable_paths(&kind)?);
+        }
+
+        Ok(all_paths)
+    }
+
+    /// Collect every existing local file and directory specified, including the contents of the
+    /// specified directories, see [collect_recursive](fn.collect_recursive.html). Paths which are
+    /// neither a file nor a directory are skipped, and returned unless they are configured paths
+    /// which do not exist and are marked as optional.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) is returned on an error determining a
+    /// system directory or root, or reading a specified directory. When strict, a path which is
+    /// skipped without being optional is an error as well.
+    pub fn local_entries(&self, roots: &Roots, strict: bool) -> Result<LocalEntries, ConfigError> {
+        let mut collected = LocalEntries::default();
+
+        for (path, entry) in self.path_entries()? {
+            let local = path.to_local_path_in(roots)?;
+
+            if entry.optional && fs::metadata(&local).is_err() {
+                continue;
+            }
+
+            collect_recursive(
+                &entry.path,
+                path.to_tar_path(),
+                local,
+                &mut vec![],
+                strict,
+                &mut collected,
+            )?;
+        }
+
+        Ok(collected)
+    }
+
+    /// Add every path specified by another [PathSpecifier](struct.PathSpecifier.html) which is not
+    /// already specified. The options of the other's entries replace those of paths which are.
+    pub fn extend(&mut self, other: &PathSpecifier) {
+        let extend = |paths: &mut Option<Vec<PathEntry>>, others: &Option<Vec<PathEntry>>| {
+            if let Some(others) = others {
+                extend_entries(paths.get_or_insert_with(Vec::new), others);
+            }
+        };
+
+        extend(&mut self.absolute, &other.absolute);
+        extend(&mut self.home, &other.home);
+        extend(&mut self.config, &other.config);
+        extend(&mut self.data, &other.data);
+        extend(&mut self.state, &other.state);
+        extend(&mut self.cache, &other.cache);
+        extend(&mut self.bin, &other.bin);
+
+        for (name, others) in &other.roots {
+            extend_entries(self.roots.entry(name.clone()).or_default(), others);
+        }
+    }
+}
+
+/// Add every entry which does not specify an existing entry's path, and replace the existing
+/// entries which do in place.
+fn extend_entries(paths: &mut Vec<PathEntry>, others: &[PathEntry]) {
+    for other in others {
+        match paths.iter_mut().find(|path| path.path == other.path) {
+            Some(path) => *path = other.clone(),
+            None => paths.push(other.clone()),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ArchivePath, PathKind};
+    use super::{expand_path, ArchivePath, PathEntry, PathKind, Roots, SkipReason};
+    use crate::configs::error::ConfigError;
     use crate::configs::path::PathSpecifier;
-    use std::path::Path;
+    use crate::configs::ConfigArchive;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn test_path_kind() {
         assert_eq!(PathKind::ABSOLUTE, PathKind::from("/etc/rconf"));
         assert_eq!(PathKind::HOME, PathKind::from("home/rconf"));
         assert_eq!(PathKind::CONFIG, PathKind::from("config/rconf"));
+        assert_eq!(PathKind::DATA, PathKind::from("data/fonts"));
+        assert_eq!(PathKind::STATE, PathKind::from("state/rconf"));
+        assert_eq!(PathKind::CACHE, PathKind::from("cache/rconf"));
+        assert_eq!(PathKind::BIN, PathKind::from("bin/rconf"));
+        assert_eq!(PathKind::ABSOLUTE, PathKind::from("binaries/rconf"));
+        assert_eq!(PathKind::ABSOLUTE, PathKind::from("absolute/etc/rconf"));
+    }
+
+    #[test]
+    fn test_expand_path() {
+        std::env::set_var("RCONF_TEST_EXPAND", "/opt/rconf");
+        std::env::remove_var("RCONF_TEST_UNSET");
+
+        let home = dirs::home_dir().unwrap();
+
+        assert_eq!(Path::new("/etc/rconf"), expand_path("/etc/rconf").unwrap());
+        assert_eq!(
+            Path::new("/etc/rconf"),
+            expand_path("/etc//rconf/").unwrap()
+        );
+        assert_eq!(home.join("x"), expand_path("~/x").unwrap());
+        assert_eq!(
+            Path::new("/opt/rconf/bin/foo"),
+            expand_path("$RCONF_TEST_EXPAND/bin/foo").unwrap()
+        );
+        assert_eq!(
+            Path::new("/opt/rconf/foo"),
+            expand_path("${RCONF_TEST_EXPAND}/foo").unwrap()
+        );
+        assert_eq!(
+            Path::new("/usr/local/foo"),
+            expand_path("${RCONF_TEST_UNSET:-/usr/local}/foo").unwrap()
+        );
+        assert_eq!(Path::new("/etc/$"), expand_path("/etc/$").unwrap());
+
+        match expand_path("$RCONF_TEST_UNSET/foo") {
+            Err(ConfigError::UnsetVariable { variable, entry }) => {
+                assert_eq!("RCONF_TEST_UNSET", variable);
+                assert_eq!("$RCONF_TEST_UNSET/foo", entry);
+            }
+            _ => panic!("expected an unset variable"),
+        }
     }
 
     #[test]
@@ -220,16 +1010,56 @@ mod tests {
         assert!(ArchivePath::from_tar_path(Path::new(".rconf")).is_none());
     }
 
+    #[test]
+    fn test_from_tar_path_skip_scripts() {
+        assert!(ArchivePath::from_tar_path(Path::new("install.sh")).is_none());
+        assert!(ArchivePath::from_tar_path(Path::new("uninstall.sh")).is_none());
+    }
+
     #[test]
     fn test_from_tar_path_absolute() {
-        let home = ArchivePath::from_tar_path(Path::new("etc/rconf"));
+        let absolute = ArchivePath::from_tar_path(Path::new("absolute/etc/rconf"));
         assert_eq!(
             ArchivePath {
                 kind: PathKind::ABSOLUTE,
-                path: Path::new("etc/rconf")
+                path: Path::new("etc/rconf").into()
             },
-            home.unwrap()
+            absolute.unwrap()
         );
+
+        // absolute paths are never stored outside of the directories of each kind
+        assert!(ArchivePath::from_tar_path(Path::new("etc/rconf")).is_none());
+    }
+
+    #[test]
+    fn test_from_legacy_tar_path() {
+        let absolute = ArchivePath::from_legacy_tar_path(Path::new("etc/rconf")).unwrap();
+        assert_eq!(PathKind::ABSOLUTE, absolute.kind);
+        assert_eq!(Path::new("absolute/etc/rconf"), absolute.to_tar_path());
+
+        let bin = ArchivePath::from_legacy_tar_path(Path::new("bin/foo")).unwrap();
+        assert_eq!(PathKind::BIN, bin.kind);
+        assert_eq!(Path::new("foo"), bin.path);
+
+        // only the directories of the other kinds were distinguished from absolute paths
+        let named = ArchivePath::from_legacy_tar_path(Path::new("absolute/foo")).unwrap();
+        assert_eq!(PathKind::ABSOLUTE, named.kind);
+        assert_eq!(Path::new("absolute/absolute/foo"), named.to_tar_path());
+        assert!(ArchivePath::from_legacy_tar_path(Path::new("install.sh")).is_none());
+    }
+
+    #[test]
+    fn test_from_unversioned_tar_path() {
+        let config = ArchivePath::from_unversioned_tar_path(Path::new("config/nvim")).unwrap();
+        assert_eq!(PathKind::CONFIG, config.kind);
+        assert_eq!(Path::new("nvim"), config.path);
+
+        // the other kinds of paths did not exist before the format was versioned
+        for tar_path in &["bin/foo", "data/fonts", "roots/workspace/.editorconfig"] {
+            let absolute = ArchivePath::from_unversioned_tar_path(Path::new(tar_path)).unwrap();
+            assert_eq!(PathKind::ABSOLUTE, absolute.kind);
+            assert_eq!(Path::new(tar_path), absolute.path);
+        }
     }
 
     #[test]
@@ -238,7 +1068,7 @@ mod tests {
         assert_eq!(
             ArchivePath {
                 kind: PathKind::HOME,
-                path: Path::new("rconf")
+                path: Path::new("rconf").into()
             },
             home.unwrap()
         );
@@ -250,27 +1080,212 @@ mod tests {
         assert_eq!(
             ArchivePath {
                 kind: PathKind::CONFIG,
-                path: Path::new("rconf")
+                path: Path::new("rconf").into()
             },
             config.unwrap()
         );
     }
 
+    #[test]
+    fn test_from_tar_path_xdg() {
+        let data = ArchivePath::from_tar_path(Path::new("data/fonts")).unwrap();
+        let bin = ArchivePath::from_tar_path(Path::new("bin/foo")).unwrap();
+
+        assert_eq!(PathKind::DATA, data.kind);
+        assert_eq!(Path::new("fonts"), data.path);
+        assert_eq!(Path::new("data/fonts"), data.to_tar_path());
+        assert_eq!(PathKind::BIN, bin.kind);
+        assert_eq!(
+            dirs::executable_dir().map(|dir| dir.join("foo")),
+            bin.to_local_path().ok()
+        );
+    }
+
+    #[test]
+    fn test_roots() {
+        let specifier: PathSpecifier =
+            toml::from_str("home = ['.bashrc']\nworkspace = ['.editorconfig']").unwrap();
+        let paths = specifier.archive_paths().unwrap();
+
+        assert_eq!(2, paths.len());
+        assert_eq!(PathKind::ROOT("workspace".to_string()), paths[1].kind);
+        assert_eq!(
+            Path::new("roots/workspace/.editorconfig"),
+            paths[1].to_tar_path()
+        );
+        assert_eq!(
+            Some(paths[1].clone()),
+            ArchivePath::from_tar_path(Path::new("roots/workspace/.editorconfig"))
+        );
+
+        let configured: BTreeMap<String, String> = vec![
+            ("workspace".to_string(), "/srv/ws".to_string()),
+            ("unset".to_string(), "$RCONF_TEST_UNSET_ROOT".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let overrides: BTreeMap<String, PathBuf> =
+            vec![("unset".to_string(), PathBuf::from("/mnt/unset"))]
+                .into_iter()
+                .collect();
+        let roots = Roots::new(&configured, &overrides).unwrap();
+
+        assert_eq!(
+            Path::new("/srv/ws/.editorconfig"),
+            paths[1].to_local_path_in(&roots).unwrap()
+        );
+        assert_eq!(Path::new("/mnt/unset"), roots.dir("unset").unwrap());
+        assert!(paths[1].to_local_path().is_err());
+    }
+
+    #[test]
+    fn test_root_names() {
+        assert!(Roots::check_name("workspace").is_ok());
+
+        for name in &["", ".", "..", "a/b", "home", "bin"] {
+            match Roots::check_name(name) {
+                Err(ConfigError::InvalidRoot(invalid)) => assert_eq!(name, &invalid),
+                _ => panic!("the root name '{}' was accepted", name),
+            }
+        }
+
+        // keys which are not a single directory name are rejected instead of becoming roots
+        assert!(toml::from_str::<PathSpecifier>("'..' = ['etc/passwd']").is_err());
+        assert!(toml::from_str::<PathSpecifier>("'work/space' = ['.editorconfig']").is_err());
+        assert!(toml::from_str::<ConfigArchive>("[roots]\nhome = '/srv/home'").is_err());
+        assert!(toml::from_str::<ConfigArchive>("[roots]\nworkspace = '/srv/ws'").is_ok());
+
+        // roots which were not deserialized, such as those of a builder, are checked when resolved
+        let configured: BTreeMap<String, String> = vec![("config".to_string(), "/srv".to_string())]
+            .into_iter()
+            .collect();
+        assert!(Roots::new(&configured, &BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_roots_prefix() {
+        let roots = Roots {
+            prefix: Some(PathBuf::from("/mnt/sys")),
+            home: Some(PathBuf::from("/mnt/sys/home/alice")),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Path::new("/mnt/sys/home/alice/.config"),
+            roots.dir_of(&PathKind::CONFIG).unwrap()
+        );
+        assert_eq!(
+            Path::new("/mnt/sys"),
+            roots.dir_of(&PathKind::ABSOLUTE).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_local_entries_missing() {
+        let dir = std::env::temp_dir().join(format!(
+            "rconf-local-entries-missing-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("present"), "").unwrap();
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let specifier = PathSpecifier {
+            absolute: Some(vec![
+                path("present").into(),
+                path("missing").into(),
+                PathEntry {
+                    optional: true,
+                    ..path("optional").into()
+                },
+            ]),
+            ..Default::default()
+        };
+        let roots = Roots::default();
+
+        let collected = specifier.local_entries(&roots, false).unwrap();
+        assert_eq!(1, collected.entries.len());
+        assert_eq!(1, collected.skipped.len());
+        assert_eq!(dir.join("missing"), collected.skipped[0].local);
+
+        match specifier.local_entries(&roots, true) {
+            Err(ConfigError::MissingPath { entry, .. }) => assert_eq!(path("missing"), entry),
+            _ => panic!("a missing path should fail when strict"),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_entries_loop() {
+        let dir =
+            std::env::temp_dir().join(format!("rconf-local-entries-loop-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("conf")).unwrap();
+        std::fs::write(dir.join("conf/file"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("conf/loop")).unwrap();
+
+        let fifo = std::ffi::CString::new(dir.join("conf/fifo").to_str().unwrap()).unwrap();
+        assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) });
+
+        let specifier = PathSpecifier {
+            absolute: Some(vec![dir.to_string_lossy().to_string().into()]),
+            ..Default::default()
+        };
+        let roots = Roots::default();
+
+        let collected = specifier.local_entries(&roots, false).unwrap();
+        let reasons: Vec<(PathBuf, SkipReason)> = collected
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.local.clone(), skipped.reason))
+            .collect();
+
+        // the directory, its subdirectory, and the file
+        assert_eq!(3, collected.entries.len());
+        assert_eq!(
+            vec![
+                (dir.join("conf/fifo"), SkipReason::Special),
+                (dir.join("conf/loop"), SkipReason::Loop)
+            ],
+            reasons
+        );
+
+        match specifier.local_entries(&roots, true) {
+            Err(ConfigError::SkippedPath { local, .. }) => assert_eq!(dir.join("conf/fifo"), local),
+            _ => panic!("a special file should fail when strict"),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_to_tar_path_absolute() {
         let absolute = ArchivePath {
             kind: PathKind::ABSOLUTE,
-            path: Path::new("etc/rconf"),
+            path: Path::new("etc/rconf").into(),
+        };
+
+        assert_eq!(Path::new("absolute/etc/rconf"), absolute.to_tar_path());
+
+        let rooted = ArchivePath {
+            kind: PathKind::ABSOLUTE,
+            path: Path::new("/bin/true").into(),
         };
 
-        assert_eq!(Path::new("etc/rconf"), absolute.to_tar_path());
+        assert_eq!(Path::new("absolute/bin/true"), rooted.to_tar_path());
+        assert_eq!(
+            Some(PathKind::ABSOLUTE),
+            ArchivePath::from_tar_path(&rooted.to_tar_path()).map(|path| path.kind)
+        );
     }
 
     #[test]
     fn test_to_tar_path_home() {
         let home = ArchivePath {
             kind: PathKind::HOME,
-            path: Path::new("rconf"),
+            path: Path::new("rconf").into(),
         };
 
         assert_eq!(Path::new("home/rconf"), home.to_tar_path());
@@ -280,7 +1295,7 @@ mod tests {
     fn test_to_tar_path_config() {
         let config = ArchivePath {
             kind: PathKind::CONFIG,
-            path: Path::new("rconf"),
+            path: Path::new("rconf").into(),
         };
 
         assert_eq!(Path::new("config/rconf"), config.to_tar_path());
@@ -289,50 +1304,87 @@ mod tests {
     #[test]
     fn test_archiveable_paths() {
         let specifier = PathSpecifier {
-            absolute: Some(vec!["/etc/rconf".to_string()]),
-            home: Some(vec!["rconf".to_string()]),
-            config: Some(vec!["rconf".to_string()]),
+            absolute: Some(vec!["/etc/rconf".into()]),
+            home: Some(vec!["rconf".into()]),
+            config: Some(vec!["rconf".into()]),
+            ..Default::default()
         };
 
         let expected_absolute = vec![ArchivePath {
             kind: PathKind::ABSOLUTE,
-            path: Path::new("/etc/rconf"),
+            path: Path::new("/etc/rconf").into(),
         }];
         let expected_home = vec![ArchivePath {
             kind: PathKind::HOME,
-            path: Path::new("rconf"),
+            path: Path::new("rconf").into(),
         }];
         let expected_config = vec![ArchivePath {
             kind: PathKind::CONFIG,
-            path: Path::new("rconf"),
+            path: Path::new("rconf").into(),
         }];
 
         assert_eq!(
             expected_absolute,
-            specifier.get_archiveable_paths(PathKind::ABSOLUTE)
+            specifier
+                .get_archiveable_paths(&PathKind::ABSOLUTE)
+                .unwrap()
         );
         assert_eq!(
             expected_home,
-            specifier.get_archiveable_paths(PathKind::HOME)
+            specifier.get_archiveable_paths(&PathKind::HOME).unwrap()
         );
         assert_eq!(
             expected_config,
-            specifier.get_archiveable_paths(PathKind::CONFIG)
+            specifier.get_archiveable_paths(&PathKind::CONFIG).unwrap()
         );
     }
 
+    #[test]
+    fn test_extend() {
+        let mut specifier = PathSpecifier {
+            absolute: None,
+            home: Some(vec!["a".into(), "b".into()]),
+            config: None,
+            ..Default::default()
+        };
+
+        let optional = PathEntry {
+            optional: true,
+            .."b".into()
+        };
+
+        specifier.extend(&PathSpecifier {
+            absolute: Some(vec!["/etc/c".into()]),
+            home: Some(vec![optional.clone(), "c".into()]),
+            config: None,
+            ..Default::default()
+        });
+
+        assert_eq!(Some(vec!["/etc/c".into()]), specifier.absolute);
+        assert_eq!(Some(vec!["a".into(), optional, "c".into()]), specifier.home);
+        assert!(specifier.config.is_none());
+    }
+
     #[test]
     fn test_empty_archiveable_paths() {
         let specifier = PathSpecifier {
             absolute: None,
             home: None,
             config: None,
+            ..Default::default()
         };
 
         assert!(specifier
-            .get_archiveable_paths(PathKind::ABSOLUTE)
+            .get_archiveable_paths(&PathKind::ABSOLUTE)
+            .unwrap()
+            .is_empty());
+        assert!(specifier
+            .get_archiveable_paths(&PathKind::HOME)
+            .unwrap()
+            .is_empty());
+        assert!(specifier
+            .get_archiveable_paths(&PathKind::CONFIG)
+            .unwrap()
             .is_empty());
-        assert!(specifier.get_archiveable_paths(PathKind::HOME).is_empty());
-        assert!(specifier.get_archiveable_paths(PathKind::CONFIG).is_empty());
     }
 }
@@ -0,0 +1,93 @@
+use super::error::{ConfigError, Result};
+use super::user::User;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The content of every file as it was last installed, used as the base of a three-way merge
+/// between the local file and a newer archived file.
+///
+/// Each file is stored beneath the state directory at its absolute local path (ex
+/// $HOME/.bashrc => data_dir/rconf/installed/home/user/.bashrc).
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstallState {
+    pub dir: PathBuf,
+
+    /// The alternate root directory files are installed beneath, which is not part of the
+    /// recorded paths so they match the paths on the installed system.
+    pub prefix: Option<PathBuf>,
+
+    /// The user given ownership of the recorded files.
+    pub owner: Option<User>,
+}
+
+impl InstallState {
+    /// Use the `rconf/installed` directory of the user's data directory.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if the data directory
+    /// could not be determined.
+    pub fn new() -> Result<InstallState> {
+        match dirs::data_dir() {
+            Some(dir) => Ok(InstallState::in_data_dir(&dir)),
+            None => Err(ConfigError::DirNotFound("Data".to_string())),
+        }
+    }
+
+    /// Use the `rconf/installed` directory of the given data directory.
+    pub fn in_data_dir(data: &Path) -> InstallState {
+        InstallState::with_dir(data.join("rconf").join("installed"))
+    }
+
+    pub fn with_dir<P: AsRef<Path>>(dir: P) -> InstallState {
+        InstallState {
+            dir: dir.as_ref().to_path_buf(),
+            prefix: None,
+            owner: None,
+        }
+    }
+
+    /// Retrieve the location in the state directory of a local file.
+    fn path_of(&self, local: &Path) -> PathBuf {
+        let local = match &self.prefix {
+            Some(prefix) => local.strip_prefix(prefix).unwrap_or(local),
+            None => local,
+        };
+
+        self.dir.join(local.strip_prefix("/").unwrap_or(local))
+    }
+
+    /// Retrieve the content of a local file as it was last installed, if it was.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// recorded content.
+    pub fn base(&self, local: &Path) -> Result<Option<Vec<u8>>> {
+        let path = self.path_of(local);
+
+        if path.is_file() {
+            Ok(Some(fs::read(path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Record the content installed to a local file by copying it from the given file.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error writing to the
+    /// state directory.
+    pub fn record(&self, local: &Path, content: &Path) -> Result<()> {
+        let path = self.path_of(local);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::copy(content, &path)?;
+
+        match &self.owner {
+            Some(owner) => owner.own(&path),
+            None => Ok(()),
+        }
+    }
+}
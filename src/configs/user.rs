@@ -0,0 +1,152 @@
+use super::error::{ConfigError, Result};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{lchown, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// The directory containing the home directory of every user.
+pub const HOMES: &str = "/home";
+
+/// The directory whose content is copied to the home directory of newly created users.
+pub const SKEL: &str = "/etc/skel";
+
+/// A local user to install configurations for, who is given ownership of the installed files
+/// beneath their home directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub home: PathBuf,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl User {
+    /// Look up a user by name in the passwd database.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned if there is no such user,
+    /// or on an error reading the passwd database.
+    pub fn by_name(name: &str) -> Result<User> {
+        let c_name = match CString::new(name) {
+            Ok(c_name) => c_name,
+            Err(_) => return Err(ConfigError::UnknownUser(name.to_string())),
+        };
+
+        let mut buf: Vec<libc::c_char> = vec![0; 4096];
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+        loop {
+            let code = unsafe {
+                libc::getpwnam_r(
+                    c_name.as_ptr(),
+                    &mut pwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+
+            match code {
+                0 => break,
+                libc::ERANGE => buf.resize(buf.len() * 2, 0),
+                code => return Err(std::io::Error::from_raw_os_error(code).into()),
+            }
+        }
+
+        if result.is_null() {
+            return Err(ConfigError::UnknownUser(name.to_string()));
+        }
+
+        let home = unsafe { CStr::from_ptr(pwd.pw_dir) };
+
+        Ok(User {
+            name: name.to_string(),
+            home: PathBuf::from(std::ffi::OsStr::from_bytes(home.to_bytes())),
+            uid: pwd.pw_uid,
+            gid: pwd.pw_gid,
+        })
+    }
+
+    /// Retrieve the users owning each directory in a directory of home directories, such as
+    /// [HOMES](constant.HOMES.html), named after their directory.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error reading the
+    /// directory.
+    pub fn all(homes: &Path) -> Result<Vec<User>> {
+        let mut users = vec![];
+
+        for entry in fs::read_dir(homes)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            if metadata.is_dir() {
+                users.push(User {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    home: entry.path(),
+                    uid: metadata.uid(),
+                    gid: metadata.gid(),
+                });
+            }
+        }
+
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(users)
+    }
+
+    /// Give the user ownership of a path and each of its parents beneath their home directory.
+    /// Paths outside of the home directory are left alone.
+    ///
+    /// # Errors
+    /// A [ConfigError](../error/enum.ConfigError.html) will be returned on an error changing the
+    /// owner of a path.
+    pub fn own(&self, path: &Path) -> Result<()> {
+        for ancestor in path.ancestors() {
+            if ancestor == self.home || !ancestor.starts_with(&self.home) {
+                break;
+            }
+
+            lchown(ancestor, Some(self.uid), Some(self.gid))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::User;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_own() {
+        let home = std::env::temp_dir().join(format!("rconf-own-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(home.join(".config/nvim")).unwrap();
+        fs::write(home.join(".config/nvim/init.vim"), "").unwrap();
+
+        let metadata = fs::metadata(&home).unwrap();
+        let user = User {
+            name: "test".to_string(),
+            home: home.clone(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+        };
+
+        user.own(&home.join(".config/nvim/init.vim")).unwrap();
+        assert!(User::all(&std::env::temp_dir())
+            .unwrap()
+            .iter()
+            .any(|user| user.home == home));
+        assert!(user.own(&home).is_ok());
+
+        assert_eq!(0, User::by_name("root").unwrap().uid);
+        assert!(User::by_name("rconf_test_no_such_user").is_err());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use super::home_relative;
+use crate::configs::error::Result;
+use crate::configs::path::{ArchivePath, ArchivedEntry, PathKind, Roots};
+use crate::configs::ConfigArchive;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The name of the generated playbook inside the export directory.
+pub const PLAYBOOK: &str = "playbook.yml";
+
+/// The directory of the export holding the archived files, this is where the `copy` module looks
+/// for relative sources.
+pub const FILES: &str = "files";
+
+/// Quote a string as a double quoted yaml scalar.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Build the templated destination of an archived path on the managed host. A root beneath the
+/// local home directory is placed beneath the home directory of the managed host.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned if the path's root
+/// is not given.
+fn destination(path: &ArchivePath, roots: &Roots) -> Result<String> {
+    let relative = path.path.to_string_lossy();
+    let relative = relative.trim_start_matches('/');
+
+    Ok(match &path.kind {
+        PathKind::ABSOLUTE => format!("/{}", relative),
+        PathKind::HOME => format!("{{{{ ansible_env.HOME }}}}/{}", relative),
+        PathKind::CONFIG => format!(
+            "{{{{ ansible_env.XDG_CONFIG_HOME | default(ansible_env.HOME + '/.config', true) }}}}/{}",
+            relative
+        ),
+        PathKind::DATA => format!(
+            "{{{{ ansible_env.XDG_DATA_HOME | default(ansible_env.HOME + '/.local/share', true) }}}}/{}",
+            relative
+        ),
+        PathKind::STATE => format!(
+            "{{{{ ansible_env.XDG_STATE_HOME | default(ansible_env.HOME + '/.local/state', true) }}}}/{}",
+            relative
+        ),
+        PathKind::CACHE => format!(
+            "{{{{ ansible_env.XDG_CACHE_HOME | default(ansible_env.HOME + '/.cache', true) }}}}/{}",
+            relative
+        ),
+        PathKind::BIN => format!(
+            "{{{{ ansible_env.XDG_BIN_HOME | default(ansible_env.HOME + '/.local/bin', true) }}}}/{}",
+            relative
+        ),
+        PathKind::ROOT(name) => {
+            let dir = roots.dir(name)?.join(relative);
+
+            match home_relative(&dir) {
+                Some(relative) => format!("{{{{ ansible_env.HOME }}}}/{}", relative.to_string_lossy()),
+                None => dir.to_string_lossy().to_string(),
+            }
+        }
+    })
+}
+
+/// Generate a playbook deploying the archived entries and installing the manager packages.
+///
+/// A `copy` task is generated for every path in the configuration which was found in the archive,
+/// with directories copied recursively. Paths outside of the user's home require elevated
+/// privileges and are copied with `become`.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned if a configured
+/// path uses an unset variable.
+pub fn build_playbook(cfg: &ConfigArchive, entries: &[ArchivedEntry]) -> Result<String> {
+    let mut playbook = String::from(
+        "---
+# generated by rconf
+- name: deploy rconf configurations
+  hosts: all
+  tasks:
+",
+    );
+
+    if let Some(manager) = &cfg.manager {
+        if !manager.packages.is_empty() {
+            playbook.push_str(
+                "    - name: install packages
+      become: true
+      package:
+        name:
+",
+            );
+
+            for package in &manager.packages {
+                playbook.push_str(&format!("          - {}\n", quote(package)));
+            }
+
+            playbook.push_str("        state: present\n");
+        }
+    }
+
+    if let Some(specifier) = &cfg.paths {
+        let roots = cfg.roots(&BTreeMap::new())?;
+
+        for path in specifier.archive_paths()? {
+            let tar_path = path.to_tar_path();
+
+            let entry = match entries.iter().find(|entry| entry.tar_path == tar_path) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            // a trailing slash copies the contents of the source directory into the destination
+            let suffix = if entry.is_dir { "/" } else { "" };
+            let src = format!("{}/{}{}", FILES, tar_path.to_string_lossy(), suffix);
+            let dest = format!("{}{}", destination(&path, &roots)?, suffix);
+
+            playbook.push_str(&format!(
+                "    - name: {}\n",
+                quote(&format!("copy {}", tar_path.to_string_lossy()))
+            ));
+
+            // templated destinations are beneath the home directory
+            if dest.starts_with('/') {
+                playbook.push_str("      become: true\n");
+            }
+
+            playbook.push_str(&format!(
+                "      copy:\n        src: {}\n        dest: {}\n",
+                quote(&src),
+                quote(&dest)
+            ));
+        }
+    }
+
+    Ok(playbook)
+}
+
+/// Write a playbook and the archived files it deploys into the given directory.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// from the archive or writing the playbook.
+pub fn write_playbook(cfg: &mut ConfigArchive, dst: &Path) -> Result<()> {
+    let files = dst.join(FILES);
+    fs::create_dir_all(&files)?;
+
+    let entries = cfg.unpack_entries(&files)?;
+    fs::write(dst.join(PLAYBOOK), build_playbook(cfg, &entries)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_playbook;
+    use crate::configs::path::ArchivedEntry;
+    use crate::configs::ConfigArchive;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_build_playbook() {
+        let cfg: ConfigArchive = toml::from_str(
+            "paths.home = ['.bashrc', '.missing']
+paths.config = ['nvim']
+paths.absolute = ['/etc/fish']
+manager.name = 'pacman'
+manager.install_args = ['-S']
+manager.packages = ['fish']",
+        )
+        .unwrap();
+
+        let entries = vec![
+            ArchivedEntry {
+                tar_path: PathBuf::from("absolute/etc/fish"),
+                is_dir: false,
+            },
+            ArchivedEntry {
+                tar_path: PathBuf::from("home/.bashrc"),
+                is_dir: false,
+            },
+            ArchivedEntry {
+                tar_path: PathBuf::from("config/nvim"),
+                is_dir: true,
+            },
+        ];
+
+        let playbook = build_playbook(&cfg, &entries).unwrap();
+
+        assert!(playbook.contains("      package:\n        name:\n          - \"fish\"\n"));
+        assert!(playbook.contains(
+            "      become: true\n      copy:\n        src: \"files/absolute/etc/fish\"\n        dest: \"/etc/fish\"\n"
+        ));
+        assert!(playbook.contains("        dest: \"{{ ansible_env.HOME }}/.bashrc\"\n"));
+        assert!(playbook.contains("        src: \"files/config/nvim/\"\n"));
+        assert!(!playbook.contains(".missing"));
+    }
+}
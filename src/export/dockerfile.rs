@@ -0,0 +1,151 @@
+use super::ExportOptions;
+use crate::configs::error::Result;
+use crate::configs::path::ArchivedEntry;
+use crate::configs::ConfigArchive;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The name of the generated Dockerfile inside the build context.
+pub const DOCKERFILE: &str = "Dockerfile";
+
+/// Format a list of words as a json array as used by the exec form of Dockerfile instructions.
+fn json_array<S: AsRef<str>>(words: &[S]) -> String {
+    let words: Vec<String> = words
+        .iter()
+        .map(|word| {
+            format!(
+                "\"{}\"",
+                word.as_ref().replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+        .collect();
+
+    format!("[{}]", words.join(", "))
+}
+
+/// Generate a Dockerfile installing the manager packages and copying the archived entries from the
+/// build context into the image.
+///
+/// The base image is taken from the `BASE_IMAGE` build argument, defaulting to the base given in
+/// the options if any.
+pub fn build_dockerfile(
+    cfg: &ConfigArchive,
+    options: &ExportOptions,
+    entries: &[ArchivedEntry],
+) -> Result<String> {
+    let mut dockerfile = String::from("# generated by rconf\n");
+
+    match &options.base {
+        Some(base) => dockerfile.push_str(&format!("ARG BASE_IMAGE={}\n", base)),
+        None => dockerfile.push_str("ARG BASE_IMAGE\n"),
+    }
+    dockerfile.push_str("FROM ${BASE_IMAGE}\n");
+
+    if let Some(manager) = &cfg.manager {
+        if !manager.packages.is_empty() {
+            let command: Vec<&String> = std::iter::once(&manager.name)
+                .chain(&manager.install_args)
+                .chain(&manager.packages)
+                .collect();
+
+            dockerfile.push_str(&format!("RUN {}\n", json_array(&command)));
+        }
+    }
+
+    let user = &options.user;
+
+    if let Some(specifier) = &cfg.paths {
+        let roots = cfg.roots(&BTreeMap::new())?;
+
+        for path in specifier.archive_paths()? {
+            let tar_path = path.to_tar_path();
+
+            if !entries.iter().any(|entry| entry.tar_path == tar_path) {
+                continue;
+            }
+
+            // only the files in the user's home are owned by them
+            let destination = user.destination(&path, &roots)?;
+            let chown = if user.name != "root" && destination.starts_with(&user.home) {
+                format!("--chown={} ", user.name)
+            } else {
+                String::new()
+            };
+
+            dockerfile.push_str(&format!(
+                "COPY {}{}\n",
+                chown,
+                json_array(&[tar_path.to_string_lossy(), destination.to_string_lossy()])
+            ));
+        }
+    }
+
+    Ok(dockerfile)
+}
+
+/// Write a Dockerfile and the archived files it copies into the given build context directory.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// from the archive or writing the build context.
+pub fn write_dockerfile(cfg: &mut ConfigArchive, options: &ExportOptions, dst: &Path) -> Result<()> {
+    let entries = cfg.unpack_entries(dst)?;
+    fs::write(dst.join(DOCKERFILE), build_dockerfile(cfg, options, &entries)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_dockerfile;
+    use crate::configs::path::ArchivedEntry;
+    use crate::configs::ConfigArchive;
+    use crate::export::{ContainerUser, ExportOptions};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_build_dockerfile() {
+        let cfg: ConfigArchive = toml::from_str(
+            "paths.home = ['.bashrc', '.missing']
+paths.config = ['nvim']
+paths.absolute = ['/etc/fish']
+manager.name = 'apt-get'
+manager.install_args = ['install', '-y']
+manager.packages = ['fish']",
+        )
+        .unwrap();
+
+        let entries = vec![
+            ArchivedEntry {
+                tar_path: PathBuf::from("absolute/etc/fish"),
+                is_dir: true,
+            },
+            ArchivedEntry {
+                tar_path: PathBuf::from("home/.bashrc"),
+                is_dir: false,
+            },
+            ArchivedEntry {
+                tar_path: PathBuf::from("config/nvim"),
+                is_dir: true,
+            },
+        ];
+
+        let options = ExportOptions {
+            user: ContainerUser::with_name("dev"),
+            base: Some("debian:stable".to_string()),
+        };
+
+        assert_eq!(
+            "# generated by rconf
+ARG BASE_IMAGE=debian:stable
+FROM ${BASE_IMAGE}
+RUN [\"apt-get\", \"install\", \"-y\", \"fish\"]
+COPY [\"absolute/etc/fish\", \"/etc/fish\"]
+COPY --chown=dev [\"home/.bashrc\", \"/home/dev/.bashrc\"]
+COPY --chown=dev [\"config/nvim\", \"/home/dev/.config/nvim\"]
+",
+            build_dockerfile(&cfg, &options, &entries).unwrap()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Translate a [ConfigArchive](../configs/struct.ConfigArchive.html) into the formats used by other
+//! provisioning tools.
+pub mod ansible;
+pub mod dockerfile;
+pub mod oci;
+
+use super::configs::error::Result;
+use super::configs::path::{ArchivePath, PathKind, Roots};
+use super::configs::ConfigArchive;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The supported export formats.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Ansible,
+    Dockerfile,
+    OciLayer,
+}
+
+impl Format {
+    /// The names of every supported format as accepted on the command line.
+    pub const NAMES: &'static [&'static str] = &["ansible", "dockerfile", "oci-layer"];
+
+    /// Parse a format from its command line name.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "ansible" => Some(Format::Ansible),
+            "dockerfile" => Some(Format::Dockerfile),
+            "oci-layer" => Some(Format::OciLayer),
+            _ => None,
+        }
+    }
+}
+
+/// Retrieve a local directory relative to the local home directory, if it is beneath it.
+fn home_relative(dir: &Path) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+
+    dir.strip_prefix(home).ok().map(Path::to_path_buf)
+}
+
+/// The user inside of a container image for which home and config paths are resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContainerUser {
+    pub name: String,
+    pub home: PathBuf,
+
+    /// The numeric user and group id to own the user's files, if any.
+    pub owner: Option<(u64, u64)>,
+}
+
+impl ContainerUser {
+    /// Create a user with the conventional home directory for the given name, `/root` for root
+    /// and `/home/<name>` for everyone else.
+    pub fn with_name(name: &str) -> ContainerUser {
+        let home = if name == "root" {
+            PathBuf::from("/root")
+        } else {
+            Path::new("/home").join(name)
+        };
+
+        ContainerUser {
+            name: name.to_string(),
+            home,
+            owner: None,
+        }
+    }
+
+    /// Retrieve the absolute location of an archived path inside the container. A root beneath the
+    /// local home directory is placed beneath the user's home.
+    ///
+    /// # Errors
+    /// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned if the path's root
+    /// is not given.
+    pub fn destination(&self, path: &ArchivePath, roots: &Roots) -> Result<PathBuf> {
+        let relative = path.path.strip_prefix("/").unwrap_or(&path.path);
+
+        Ok(match &path.kind {
+            PathKind::ABSOLUTE => Path::new("/").join(relative),
+            PathKind::HOME => self.home.join(relative),
+            PathKind::CONFIG => self.home.join(".config").join(relative),
+            PathKind::DATA => self.home.join(".local/share").join(relative),
+            PathKind::STATE => self.home.join(".local/state").join(relative),
+            PathKind::CACHE => self.home.join(".cache").join(relative),
+            PathKind::BIN => self.home.join(".local/bin").join(relative),
+            PathKind::ROOT(name) => {
+                let dir = roots.dir(name)?.join(relative);
+
+                match home_relative(&dir) {
+                    Some(relative) => self.home.join(relative),
+                    None => dir,
+                }
+            }
+        })
+    }
+}
+
+impl Default for ContainerUser {
+    fn default() -> ContainerUser {
+        ContainerUser::with_name("root")
+    }
+}
+
+/// Export options which only apply to some formats.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportOptions {
+    /// The user to deploy the configurations for, used by the container formats.
+    pub user: ContainerUser,
+
+    /// The default base image of a generated Dockerfile.
+    pub base: Option<String>,
+}
+
+/// Export the archive in the given format into the destination directory, creating it if
+/// necessary.
+///
+/// # Errors
+/// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// from the archive or writing the exported files.
+pub fn export(
+    cfg: &mut ConfigArchive,
+    format: Format,
+    options: &ExportOptions,
+    dst: &Path,
+) -> Result<()> {
+    fs::create_dir_all(dst)?;
+
+    match format {
+        Format::Ansible => ansible::write_playbook(cfg, dst),
+        Format::Dockerfile => dockerfile::write_dockerfile(cfg, options, dst),
+        Format::OciLayer => oci::write_layer(cfg, &options.user, dst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContainerUser;
+    use crate::configs::path::{ArchivePath, PathKind, Roots};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_container_user_home() {
+        assert_eq!(PathBuf::from("/root"), ContainerUser::with_name("root").home);
+        assert_eq!(PathBuf::from("/home/dev"), ContainerUser::with_name("dev").home);
+    }
+
+    #[test]
+    fn test_container_destination() {
+        let user = ContainerUser::with_name("dev");
+
+        let absolute = ArchivePath {
+            kind: PathKind::ABSOLUTE,
+            path: Path::new("/etc/fish").into(),
+        };
+        let config = ArchivePath {
+            kind: PathKind::CONFIG,
+            path: Path::new("nvim").into(),
+        };
+
+        let mut roots = Roots::default();
+        roots.dirs.insert("company".to_string(), "/opt/company".into());
+
+        let root = ArchivePath {
+            kind: PathKind::ROOT("company".to_string()),
+            path: Path::new("tools.conf").into(),
+        };
+
+        assert_eq!(PathBuf::from("/etc/fish"), user.destination(&absolute, &roots).unwrap());
+        assert_eq!(
+            PathBuf::from("/home/dev/.config/nvim"),
+            user.destination(&config, &roots).unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("/opt/company/tools.conf"),
+            user.destination(&root, &roots).unwrap()
+        );
+    }
+}
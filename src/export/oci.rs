@@ -0,0 +1,143 @@
+use super::ContainerUser;
+use crate::configs::error::{ConfigError, Result};
+use crate::configs::ConfigArchive;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tar::{Builder, EntryType, Header};
+
+/// The name of the generated layer tarball inside the export directory.
+pub const LAYER: &str = "layer.tar";
+
+/// Generate the header for a directory implicitly created by the layer.
+fn dir_header(owner: Option<(u64, u64)>) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+
+    if let Some((uid, gid)) = owner {
+        header.set_uid(uid);
+        header.set_gid(gid);
+    }
+
+    header
+}
+
+/// Write a filesystem layer tarball to the given directory with every archived entry rooted at
+/// its location inside of the container for the given user.
+///
+/// Any missing parent directories are added to the layer, and when the user has an owner every
+/// entry beneath their home directory is owned by it.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// from the archive or writing the layer.
+pub fn write_layer(cfg: &mut ConfigArchive, user: &ContainerUser, dst: &Path) -> Result<()> {
+    let mut builder = Builder::new(File::create(dst.join(LAYER))?);
+
+    let home = user
+        .home
+        .strip_prefix("/")
+        .unwrap_or(&user.home)
+        .to_path_buf();
+    let owner_of = |path: &Path| {
+        if path.starts_with(&home) {
+            user.owner
+        } else {
+            None
+        }
+    };
+
+    let roots = cfg.roots(&BTreeMap::new())?;
+    let mut written: HashSet<PathBuf> = HashSet::new();
+
+    cfg.for_each_entry(|path, entry| {
+        let destination = match user.destination(path, &roots)? {
+            destination if destination.is_absolute() => {
+                destination.strip_prefix("/").unwrap().to_path_buf()
+            }
+            destination => {
+                return Err(ConfigError::RelativeDestination {
+                    path: path.to_tar_path(),
+                    destination,
+                })
+            }
+        };
+
+        // add any missing parents from the root down
+        let mut parents: Vec<&Path> = destination
+            .ancestors()
+            .skip(1)
+            .filter(|parent| *parent != Path::new(""))
+            .collect();
+        parents.reverse();
+
+        for parent in parents {
+            if written.insert(parent.to_path_buf()) {
+                builder.append_data(&mut dir_header(owner_of(parent)), parent, std::io::empty())?;
+            }
+        }
+
+        if !written.insert(destination.clone()) {
+            return Ok(());
+        }
+
+        let mut header = entry.header().clone();
+        if let Some((uid, gid)) = owner_of(&destination) {
+            header.set_uid(uid);
+            header.set_gid(gid);
+        }
+
+        builder.append_data(&mut header, &destination, entry)?;
+
+        Ok(())
+    })?;
+
+    builder.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_layer;
+    use crate::configs::error::ConfigError;
+    use crate::configs::ConfigArchive;
+    use crate::export::ContainerUser;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use tar::{Builder, Header};
+
+    #[test]
+    fn test_relative_home() {
+        let mut builder = Builder::new(vec![]);
+        let mut header = Header::new_gnu();
+        header.set_size(0);
+        builder
+            .append_data(&mut header, "home/.bashrc", std::io::empty())
+            .unwrap();
+
+        let mut cfg =
+            ConfigArchive::with_plain_reader(Cursor::new(builder.into_inner().unwrap())).unwrap();
+        let user = ContainerUser {
+            home: PathBuf::from("home/dev"),
+            ..ContainerUser::with_name("dev")
+        };
+
+        let dir =
+            std::env::temp_dir().join(format!("rconf-oci-relative-home-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        match write_layer(&mut cfg, &user, &dir) {
+            Err(ConfigError::RelativeDestination { destination, .. }) => {
+                assert_eq!(PathBuf::from("home/dev/.bashrc"), destination)
+            }
+            _ => panic!("a relative destination should be rejected"),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
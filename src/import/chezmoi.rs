@@ -0,0 +1,126 @@
+use crate::configs::error::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Source attribute prefixes which do not change the target name.
+const ATTRIBUTES: &[&str] = &[
+    "create_",
+    "symlink_",
+    "encrypted_",
+    "external_",
+    "exact_",
+    "private_",
+    "readonly_",
+    "empty_",
+    "executable_",
+];
+
+/// Source prefixes for entries which are not files in the home directory.
+const SKIPPED: &[&str] = &["run_", "modify_", "remove_"];
+
+/// Source suffixes which do not change the target name.
+const SUFFIXES: &[&str] = &[".tmpl", ".literal", ".age", ".asc"];
+
+/// Translate a chezmoi source name into the name of its target, or none if the source entry does
+/// not correspond to a file in the home directory.
+pub fn target_name(name: &str) -> Option<String> {
+    // dot prefixed entries are ignored or special to chezmoi
+    if name.starts_with('.') || SKIPPED.iter().any(|prefix| name.starts_with(prefix)) {
+        return None;
+    }
+
+    let mut name = name;
+
+    while let Some(rest) = ATTRIBUTES
+        .iter()
+        .find_map(|attribute| name.strip_prefix(attribute))
+    {
+        name = rest;
+    }
+
+    for suffix in SUFFIXES {
+        if let Some(rest) = name.strip_suffix(suffix) {
+            name = rest;
+        }
+    }
+
+    if let Some(rest) = name.strip_prefix("literal_") {
+        Some(rest.to_string())
+    } else if let Some(rest) = name.strip_prefix("dot_") {
+        Some(format!(".{}", rest))
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Retrieve the paths relative to the home directory which are managed by a chezmoi source
+/// directory, respecting a `.chezmoiroot` file.
+///
+/// Every top level entry is a path, except for the `.config` directory whose entries are each
+/// their own path.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// the source directory.
+pub fn home_paths(source: &Path) -> Result<Vec<PathBuf>> {
+    let root = source.join(".chezmoiroot");
+    let source = if root.is_file() {
+        source.join(fs::read_to_string(root)?.trim())
+    } else {
+        source.to_path_buf()
+    };
+
+    let mut paths = vec![];
+
+    for entry in fs::read_dir(&source)? {
+        let entry = entry?;
+
+        let target = match target_name(&entry.file_name().to_string_lossy()) {
+            Some(target) => PathBuf::from(target),
+            None => continue,
+        };
+
+        if target == Path::new(".config") && entry.path().is_dir() {
+            for child in fs::read_dir(entry.path())? {
+                if let Some(child) = target_name(&child?.file_name().to_string_lossy()) {
+                    paths.push(target.join(child));
+                }
+            }
+        } else {
+            paths.push(target);
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::target_name;
+
+    #[test]
+    fn test_target_name() {
+        assert_eq!(Some(".bashrc".to_string()), target_name("dot_bashrc"));
+        assert_eq!(
+            Some(".ssh".to_string()),
+            target_name("private_dot_ssh")
+        );
+        assert_eq!(
+            Some(".gitconfig".to_string()),
+            target_name("private_dot_gitconfig.tmpl")
+        );
+        assert_eq!(Some("dot_x".to_string()), target_name("literal_dot_x"));
+        assert_eq!(
+            Some(".vim".to_string()),
+            target_name("exact_private_dot_vim")
+        );
+    }
+
+    #[test]
+    fn test_target_name_skipped() {
+        assert!(target_name(".chezmoiignore").is_none());
+        assert!(target_name(".git").is_none());
+        assert!(target_name("run_once_install.sh").is_none());
+        assert!(target_name("modify_dot_profile").is_none());
+    }
+}
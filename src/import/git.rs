@@ -0,0 +1,83 @@
+use crate::configs::error::{ConfigError, Result};
+use crate::configs::manager::STDERR_TAIL;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Retrieve the paths relative to the home directory of every file tracked by a bare git
+/// repository, such as those used by yadm or `git --git-dir=$HOME/.dotfiles --work-tree=$HOME`.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned if git could not be
+/// run or failed to list the tracked files.
+pub fn home_paths(repository: &Path) -> Result<Vec<PathBuf>> {
+    let git_dir = format!("--git-dir={}", repository.to_string_lossy());
+    let args = vec![
+        git_dir,
+        "ls-tree".to_string(),
+        "-r".to_string(),
+        "-z".to_string(),
+        "--name-only".to_string(),
+        "HEAD".to_string(),
+    ];
+
+    let output = Command::new("git")
+        .args(&args)
+        .output()
+        .map_err(|source| ConfigError::Spawn {
+            command: "git".to_string(),
+            args: args.clone(),
+            source,
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+
+        return Err(ConfigError::Exit {
+            command: "git".to_string(),
+            args,
+            code: output.status.code(),
+            stderr: lines[lines.len().saturating_sub(STDERR_TAIL)..].join("\n"),
+        });
+    }
+
+    Ok(split_paths(&output.stdout))
+}
+
+/// Split the NUL terminated paths listed by git, which are neither quoted nor escaped.
+fn split_paths(listed: &[u8]) -> Vec<PathBuf> {
+    listed
+        .split(|byte| *byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{home_paths, split_paths};
+    use crate::configs::error::ConfigError;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_split_paths() {
+        assert_eq!(
+            vec![PathBuf::from(".config/café"), PathBuf::from("a \"b\"\nc")],
+            split_paths(".config/café\0a \"b\"\nc\0".as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_home_paths_failure() {
+        match home_paths(Path::new("/nonexistent/rconf-test-repository")) {
+            Err(ConfigError::Exit { code, stderr, .. }) => {
+                assert_ne!(Some(0), code);
+                assert!(!stderr.is_empty());
+            }
+            Err(ConfigError::Spawn { .. }) => {} // git is not installed
+            _ => panic!("listing a missing repository should fail"),
+        }
+    }
+}
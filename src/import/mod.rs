@@ -0,0 +1,120 @@
+//! Build a [PathSpecifier](../configs/path/struct.PathSpecifier.html) from the files managed by
+//! other dotfile managers.
+pub mod chezmoi;
+pub mod git;
+pub mod stow;
+
+use super::configs::error::Result;
+use super::configs::path::{PathEntry, PathSpecifier};
+use std::path::{Component, Path, PathBuf};
+
+/// The supported dotfile managers to import from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Source {
+    Stow,
+    Chezmoi,
+    BareGit,
+}
+
+impl Source {
+    /// The names of every supported source as accepted on the command line, `yadm` repositories
+    /// are bare git repositories.
+    pub const NAMES: &'static [&'static str] = &["stow", "chezmoi", "bare-git", "yadm"];
+
+    /// Parse a source from its command line name.
+    pub fn from_name(name: &str) -> Option<Source> {
+        match name {
+            "stow" => Some(Source::Stow),
+            "chezmoi" => Some(Source::Chezmoi),
+            "bare-git" | "yadm" => Some(Source::BareGit),
+            _ => None,
+        }
+    }
+}
+
+/// Build a [PathSpecifier](../configs/path/struct.PathSpecifier.html) from paths relative to the
+/// home directory. Paths beneath `.config` are specified relative to the config directory instead.
+pub fn specifier_from_home_paths<I>(paths: I) -> PathSpecifier
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    let mut home = vec![];
+    let mut config = vec![];
+
+    for path in paths {
+        let mut components = path.components();
+
+        match components.next() {
+            Some(Component::Normal(first)) if first == ".config" => {
+                let rest = components.as_path();
+
+                if rest == Path::new("") {
+                    home.push(path.to_string_lossy().to_string());
+                } else {
+                    config.push(rest.to_string_lossy().to_string());
+                }
+            }
+            _ => home.push(path.to_string_lossy().to_string()),
+        }
+    }
+
+    let finalize = |mut paths: Vec<String>| {
+        paths.sort();
+        paths.dedup();
+
+        if paths.is_empty() {
+            None
+        } else {
+            Some(paths.into_iter().map(PathEntry::from).collect())
+        }
+    };
+
+    PathSpecifier {
+        home: finalize(home),
+        config: finalize(config),
+        ..Default::default()
+    }
+}
+
+/// Import the files managed in the given directories as a
+/// [PathSpecifier](../configs/path/struct.PathSpecifier.html).
+///
+/// # Errors
+/// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// the directories or querying the dotfile manager.
+pub fn import(source: Source, dirs: &[&Path]) -> Result<PathSpecifier> {
+    let mut paths = vec![];
+
+    for dir in dirs {
+        paths.append(&mut match source {
+            Source::Stow => stow::home_paths(dir)?,
+            Source::Chezmoi => chezmoi::home_paths(dir)?,
+            Source::BareGit => git::home_paths(dir)?,
+        });
+    }
+
+    Ok(specifier_from_home_paths(paths))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::specifier_from_home_paths;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_specifier_from_home_paths() {
+        let specifier = specifier_from_home_paths(vec![
+            PathBuf::from(".bashrc"),
+            PathBuf::from(".config/nvim"),
+            PathBuf::from(".config"),
+            PathBuf::from(".bashrc"),
+        ]);
+
+        assert_eq!(
+            Some(vec![".bashrc".into(), ".config".into()]),
+            specifier.home
+        );
+        assert_eq!(Some(vec!["nvim".into()]), specifier.config);
+        assert!(specifier.absolute.is_none());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::configs::error::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files stow ignores in a package by default.
+const IGNORED: &[&str] = &[".git", ".gitignore", ".stow-local-ignore", "README", "LICENSE", "COPYING"];
+
+/// Translate a name from a stow package into its target name, expanding the `dot-` prefix used
+/// by `stow --dotfiles`.
+fn target_name(name: &str) -> String {
+    match name.strip_prefix("dot-") {
+        Some(rest) => format!(".{}", rest),
+        None => name.to_string(),
+    }
+}
+
+/// Whether stow ignores the entry of a package by default.
+fn is_ignored(name: &str) -> bool {
+    IGNORED
+        .iter()
+        .any(|ignored| name == *ignored || name.starts_with(&format!("{}.", ignored)))
+}
+
+/// Retrieve the paths relative to the home directory which are installed by a stow package.
+///
+/// Every top level entry of the package is a path, except for the `.config` directory whose
+/// entries are each their own path.
+///
+/// # Errors
+/// A [ConfigError](../../configs/error/enum.ConfigError.html) will be returned on an error reading
+/// the package directory.
+pub fn home_paths(package: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+
+    for entry in fs::read_dir(package)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if is_ignored(&name) {
+            continue;
+        }
+
+        let target = PathBuf::from(target_name(&name));
+
+        if target == Path::new(".config") && entry.path().is_dir() {
+            for child in fs::read_dir(entry.path())? {
+                let child = child?.file_name().to_string_lossy().to_string();
+                paths.push(target.join(target_name(&child)));
+            }
+        } else {
+            paths.push(target);
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_ignored, target_name};
+
+    #[test]
+    fn test_target_name() {
+        assert_eq!(".bashrc", target_name("dot-bashrc"));
+        assert_eq!(".bashrc", target_name(".bashrc"));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        assert!(is_ignored(".git"));
+        assert!(is_ignored("README.md"));
+        assert!(!is_ignored(".gitconfig"));
+    }
+}
@@ -0,0 +1,48 @@
+//! Package, distribute, and deploy system configurations.
+//!
+//! A [ConfigArchive](configs/struct.ConfigArchive.html) describes the configuration files to
+//! archive with a [PathSpecifier](configs/path/struct.PathSpecifier.html) and the packages to
+//! install with a [Manager](configs/manager/struct.Manager.html). It can be read from a toml
+//! configuration file or an existing archive, or built programmatically:
+//!
+//! ```no_run
+//! use rconf::{ConfigBuilder, ManagerBuilder};
+//! use std::path::Path;
+//!
+//! # fn main() -> rconf::Result<()> {
+//! let cfg = ConfigBuilder::new()
+//!     .home(".bashrc")
+//!     .config("nvim")
+//!     .absolute("/etc/gitconfig")
+//!     .manager(
+//!         ManagerBuilder::new("pacman")
+//!             .install_args(&["-S", "--noconfirm"])
+//!             .package("neovim")
+//!             .build(),
+//!     )
+//!     .build();
+//!
+//! cfg.write_archive(Path::new("rconf.tar"), &rconf::ArchiveOptions::default())?;
+//!
+//! let mut archive = rconf::ConfigArchive::with_archive("rconf.tar")?;
+//! archive.install(&rconf::InstallOptions::default())?;
+//! # Ok(())
+//! # }
+//! ```
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+pub mod check;
+pub mod configs;
+pub mod export;
+pub mod import;
+pub mod report;
+pub mod script;
+
+pub use configs::builder::{ConfigBuilder, ManagerBuilder};
+pub use configs::conflict::ConflictPolicy;
+pub use configs::error::{ConfigError, Result};
+pub use configs::manager::Manager;
+pub use configs::path::{ArchivePath, PathKind, PathSpecifier};
+pub use configs::{ArchiveOptions, ConfigArchive, InstallOptions};
@@ -1,23 +1,28 @@
 //! Command line tool intended to ease the burden of a system setup and configuration and allow
 //! users to hit the ground running.
-mod configs;
-mod script;
-
-#[macro_use]
-extern crate serde_derive;
-extern crate toml;
-
 use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
-use configs::{error::ConfigError, ConfigArchive};
+use rconf::configs::conflict::{ConflictPolicy, Outcome};
+use rconf::configs::overlay::{Layer, Overlay};
+use rconf::configs::path::{Roots, SkipReason, SkippedPath};
+use rconf::configs::user::{self, User};
+use rconf::report::{Action, EntryReport, Report};
+use rconf::{check, export, import, ArchiveOptions, ConfigArchive, ConfigError, InstallOptions};
+use std::fs::File;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
-/// Create a tar archive of existing system config files specified in the given toml file. Defaults
-/// to a '.rconf' file in the home directory.
-fn archive(archive_matches: &ArgMatches) -> Result<(), ConfigError> {
-    // determine the path to the configuration file
+/// The formats of the output written by each command.
+const OUTPUT_FORMATS: &[&str] = &["text", "json"];
+
+/// The archive path standing for stdin or stdout.
+const STDIO: &str = "-";
+
+/// Determine the path to the configuration file, defaulting to a '.rconf' file in the config
+/// directory.
+fn config_file(matches: &ArgMatches) -> Result<PathBuf, ConfigError> {
     let mut path = PathBuf::new();
 
-    match archive_matches.value_of("config_file") {
+    match matches.value_of("config_file") {
         Some(p) => path.push(p),
         // add the default configuration path
         None => match dirs::config_dir() {
@@ -26,15 +31,85 @@ fn archive(archive_matches: &ArgMatches) -> Result<(), ConfigError> {
                 path.push(".rconf");
             }
             None => {
-                eprintln!("Could not determine default configuration directory, and no config file was given.");
-                std::process::exit(1);
+                return Err(ConfigError::DirNotFound(String::from(
+                    "config directory, and no config file was given",
+                )))
             }
         },
     };
 
-    // print error message and exit
+    Ok(path)
+}
+
+/// Create a tar archive of existing system config files specified in the given toml file. Defaults
+/// to a '.rconf' file in the home directory.
+fn archive(
+    archive_matches: &ArgMatches,
+    report: &mut Report,
+    text: bool,
+) -> Result<(), ConfigError> {
+    // determine the path to the configuration file
+    let path = config_file(archive_matches)?;
+
     let cfg = ConfigArchive::with_file(&path)?;
 
+    let options = ArchiveOptions {
+        strict: archive_matches.is_present("strict"),
+    };
+    let base = archive_matches.value_of("since").map(Path::new);
+
+    let archived = if archive_matches.value_of("to") == Some(STDIO) {
+        // the archive is the only thing written to stdout
+        if !text {
+            return Err(ConfigError::Argument(
+                "A json report cannot be written to stdout along with the archive".to_string(),
+            ));
+        }
+
+        let stdout = std::io::stdout();
+
+        match base {
+            Some(base) => cfg.write_archive_since_to(stdout.lock(), base, &options)?,
+            None => cfg.write_archive_to(stdout.lock(), &options)?,
+        }
+    } else {
+        let path = archive_destination(archive_matches)?;
+
+        match base {
+            Some(base) => cfg.write_archive_since(&path, base, &options)?,
+            None => cfg.write_archive(&path, &options)?,
+        }
+    };
+
+    report.add_entries(&archived.archived, Action::Archived);
+    report_skipped(report, &archived.skipped);
+
+    if text {
+        print_skipped(&archived.skipped);
+    }
+
+    Ok(())
+}
+
+/// Add every skipped path to the report, as missing if it does not exist.
+fn report_skipped(report: &mut Report, skipped: &[SkippedPath]) {
+    for skipped in skipped {
+        let action = match skipped.reason {
+            SkipReason::Missing => Action::Missing,
+            _ => Action::Skipped,
+        };
+
+        report.add_paths([&skipped.tar_path], action);
+    }
+}
+
+/// Determine the path to write an archive to, either given as is or as a title within the
+/// destination directory.
+fn archive_destination(archive_matches: &ArgMatches) -> Result<PathBuf, ConfigError> {
+    if let Some(to) = archive_matches.value_of("to") {
+        return Ok(PathBuf::from(to));
+    }
+
     // determine the destination path
     let mut path = PathBuf::new();
 
@@ -45,10 +120,7 @@ fn archive(archive_matches: &ArgMatches) -> Result<(), ConfigError> {
     }
 
     // add the '.tar' extension if necessary to the given archive name
-    let mut title = String::from(match archive_matches.value_of("title") {
-        Some(title) => title,
-        None => "rconf.tar",
-    });
+    let mut title = String::from(archive_matches.value_of("title").unwrap_or("rconf.tar"));
 
     if !title.ends_with(".tar") {
         title.push_str(".tar");
@@ -57,46 +129,464 @@ fn archive(archive_matches: &ArgMatches) -> Result<(), ConfigError> {
     // add tile to the given path
     path.push(title);
 
-    cfg.write_archive(path.as_path())?;
+    Ok(path)
+}
+
+/// Warn about every path which was skipped because it does not exist or could not be archived.
+fn print_skipped(skipped: &[SkippedPath]) {
+    for skipped in skipped {
+        eprintln!(
+            "warning: skipped {} of '{}' which {}",
+            skipped.local.display(),
+            skipped.entry,
+            skipped.reason
+        );
+    }
+}
+
+/// Open the archives to install in order of increasing precedence, reading the archive given as -
+/// from stdin. Plain archives without a manifest are installed as they are.
+fn open_overlay(tar_paths: &[&str], plain: bool) -> Result<Overlay, ConfigError> {
+    let mut layers = vec![];
+
+    for tar_path in tar_paths {
+        let (name, reader): (&str, Box<dyn Read>) = match *tar_path {
+            STDIO => ("stdin", Box::new(std::io::stdin())),
+            tar_path => (tar_path, Box::new(File::open(tar_path)?)),
+        };
+
+        let cfg = if plain {
+            ConfigArchive::with_plain_reader(reader)?
+        } else {
+            ConfigArchive::with_reader(reader)?
+        };
+
+        layers.push(Layer {
+            name: name.to_string(),
+            cfg,
+        });
+    }
+
+    Ok(Overlay { layers })
+}
+
+/// Create the options to install with for each user the files are installed for, only upgrading
+/// the system and installing the packages for the first.
+fn install_targets(
+    install_matches: &ArgMatches,
+    options: InstallOptions,
+) -> Result<Vec<InstallOptions>, ConfigError> {
+    // home directories are located beneath the alternate root directory if any
+    let rebase = |dir: &Path| match &options.prefix {
+        Some(prefix) => prefix.join(dir.strip_prefix("/").unwrap_or(dir)),
+        None => dir.to_path_buf(),
+    };
+
+    let users = if let Some(name) = install_matches.value_of("user") {
+        let user = User::by_name(name)?;
+        vec![User {
+            home: rebase(&user.home),
+            ..user
+        }]
+    } else if install_matches.is_present("all_users") {
+        User::all(&rebase(Path::new(user::HOMES)))?
+    } else if install_matches.is_present("skel") {
+        return Ok(vec![InstallOptions {
+            home: Some(rebase(Path::new(user::SKEL))),
+            skeleton: true,
+            ..options
+        }]);
+    } else {
+        return Ok(vec![options]);
+    };
+
+    Ok(users
+        .into_iter()
+        .enumerate()
+        .map(|(index, user)| InstallOptions {
+            owner: Some(user),
+            skip_packages: options.skip_packages || index > 0,
+            upgrade: options.upgrade && index == 0,
+            ..options.clone()
+        })
+        .collect())
+}
+
+/// Install an overlay of archives, adding the installed files to the report.
+fn install_overlay(
+    overlay: &mut Overlay,
+    options: &InstallOptions,
+    report: &mut Report,
+) -> Result<(), ConfigError> {
+    let installed = overlay.install(options)?;
+
+    report.invocations.extend(installed.invocations);
+    report.overridden.extend(installed.overridden);
+
+    for installed in installed.installed {
+        let action = match installed.outcome {
+            Outcome::Skipped => Action::Skipped,
+            Outcome::Merged(_) => Action::Merged,
+            _ => Action::Installed,
+        };
+
+        if let Some(mut entry) = EntryReport::new(&installed.path, action) {
+            entry.destination = Some(installed.destination);
+            entry.layer = Some(installed.layer);
+
+            match installed.outcome {
+                Outcome::BackedUp(backup) => entry.backup = Some(backup),
+                Outcome::Merged(conflicts) => entry.conflicts = conflicts,
+                _ => {}
+            }
+
+            report.entries.push(entry);
+        }
+    }
+
+    for removed in installed.removed {
+        if let Some(mut entry) = EntryReport::new(&removed.path, Action::Removed) {
+            entry.destination = Some(removed.destination);
+            report.entries.push(entry);
+        }
+    }
 
     Ok(())
 }
 
-fn install(install_matches: &ArgMatches) -> Result<(), ConfigError> {
-    let tar_path = Path::new(install_matches.value_of("archive").unwrap());
-    let mut archive_cfg = ConfigArchive::with_archive(tar_path)?;
+/// Check a configuration file for mistakes, failing if any problem is found.
+fn check(
+    check_matches: &ArgMatches,
+    report: &mut Report,
+    text: bool,
+) -> Result<(), ConfigError> {
+    let path = config_file(check_matches)?;
+    report.problems = check::check(&path, check_matches.is_present("upgrade"))?;
 
-    if install_matches.is_present("upgrade") {
-        let is_upgraded: Result<(), ConfigError> = match &archive_cfg.manager {
-            Some(manager) => {
-                if let Err(err) = manager.system_upgrade() {
-                    Err(err)
-                } else {
-                    Ok(())
-                }
+    if text {
+        for problem in &report.problems {
+            match problem.line {
+                Some(line) => println!("{}:{}: {}", problem.file.display(), line, problem.message),
+                None => println!("{}: {}", problem.file.display(), problem.message),
             }
-            None => Err(ConfigError::FieldNotFound(String::from("manager"))),
-        };
+        }
+    }
 
-        if let Err(err) = is_upgraded {
-            return Err(err);
+    if report.problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Problems(report.problems.len()))
+    }
+}
+
+fn install(
+    install_matches: &ArgMatches,
+    report: &mut Report,
+    text: bool,
+) -> Result<(), ConfigError> {
+    let tar_paths: Vec<&str> = install_matches.values_of("archive").unwrap().collect();
+
+    let mut options = InstallOptions::default();
+
+    if let Some(policy) = install_matches.value_of("on_conflict") {
+        // the possible values are restricted by clap
+        options.on_conflict = ConflictPolicy::from_name(policy).unwrap();
+    }
+
+    options.merge = install_matches.is_present("merge");
+
+    for root in install_matches.values_of("root").into_iter().flatten() {
+        match root.split_once('=') {
+            Some((name, dir)) if !name.is_empty() => {
+                Roots::check_name(name)?;
+                options.roots.insert(name.to_string(), PathBuf::from(dir));
+            }
+            None if options.prefix.is_none() => options.prefix = Some(PathBuf::from(root)),
+            _ => {
+                return Err(ConfigError::Argument(format!(
+                    "Invalid root '{}', expected NAME=DIR or a single directory",
+                    root
+                )))
+            }
         }
     }
 
-    archive_cfg.install()
+    options.home = install_matches.value_of("home").map(PathBuf::from);
+    options.skip_packages = install_matches.is_present("no_packages");
+    options.upgrade = install_matches.is_present("upgrade");
+    options.escalate = install_matches
+        .value_of("escalate")
+        .map(|command| command.split_whitespace().map(String::from).collect());
+    options.archive_from_stdin = tar_paths.contains(&STDIO);
+
+    let targets = install_targets(install_matches, options)?;
+
+    match tar_paths.iter().filter(|tar_path| **tar_path == STDIO).count() {
+        0 => {}
+        1 if targets.len() > 1 => {
+            return Err(ConfigError::Argument(
+                "An archive read from stdin can only be installed for a single user".to_string(),
+            ))
+        }
+        1 => {}
+        _ => {
+            return Err(ConfigError::Argument(
+                "Only one archive can be read from stdin".to_string(),
+            ))
+        }
+    }
+
+    for options in &targets {
+        // every entry of an archive can only be read once, so it is opened for each user
+        let mut overlay = open_overlay(&tar_paths, install_matches.is_present("plain"))?;
+        install_overlay(&mut overlay, options, report)?;
+    }
+
+    if !text {
+        return Ok(());
+    }
+
+    // only report the origin of each file when there is more than one possible origin
+    let layered = tar_paths.len() > 1;
+
+    for entry in &report.entries {
+        let destination = entry.destination.as_deref().unwrap_or(&entry.path);
+
+        match entry.action {
+            Action::Installed if layered => println!(
+                "{} -> {} from {}",
+                entry.path.display(),
+                destination.display(),
+                entry.layer.as_deref().unwrap_or_default()
+            ),
+            Action::Removed if layered => println!("removed {}", destination.display()),
+            Action::Skipped => println!("kept {}", destination.display()),
+            Action::Merged if entry.conflicts.is_empty() => {
+                println!("merged {}", destination.display())
+            }
+            Action::Merged => println!(
+                "merged {} with conflicts at lines {}",
+                destination.display(),
+                entry
+                    .conflicts
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            _ => {}
+        }
+
+        if let Some(backup) = &entry.backup {
+            println!("backed up {} to {}", destination.display(), backup.display());
+        }
+    }
+
+    if layered {
+        print_overridden(report);
+    }
+
+    Ok(())
 }
 
-fn remove(remove_matches: &ArgMatches) -> Result<(), ConfigError> {
+fn remove(
+    remove_matches: &ArgMatches,
+    report: &mut Report,
+    _text: bool,
+) -> Result<(), ConfigError> {
     let tar_path = Path::new(remove_matches.value_of("archive").unwrap());
     let mut archive_cfg = ConfigArchive::with_archive(tar_path)?;
 
-    archive_cfg.uninstall()
+    let uninstalled = archive_cfg.uninstall()?;
+
+    report.invocations.extend(uninstalled.invocations);
+    report.add_entries(&uninstalled.removed, Action::Removed);
+
+    Ok(())
+}
+
+/// Export an archive into a format understood by another provisioning tool.
+fn export(
+    export_matches: &ArgMatches,
+    _report: &mut Report,
+    _text: bool,
+) -> Result<(), ConfigError> {
+    let tar_path = Path::new(export_matches.value_of("archive").unwrap());
+    let mut archive_cfg = ConfigArchive::with_archive(tar_path)?;
+
+    // the possible values are restricted by clap
+    let format = export::Format::from_name(export_matches.value_of("format").unwrap()).unwrap();
+
+    let dst = match export_matches.value_of("destination") {
+        Some(dst) => PathBuf::from(dst),
+        None => std::env::current_dir()?,
+    };
+
+    let mut options = export::ExportOptions::default();
+
+    if let Some(name) = export_matches.value_of("user") {
+        options.user = export::ContainerUser::with_name(name);
+    }
+
+    if let Some(home) = export_matches.value_of("home") {
+        if !Path::new(home).is_absolute() {
+            return Err(ConfigError::Argument(format!(
+                "Invalid home '{}', expected an absolute path inside the container",
+                home
+            )));
+        }
+
+        options.user.home = PathBuf::from(home);
+    }
+
+    if let Some(owner) = export_matches.value_of("owner") {
+        let ids: Vec<Option<u64>> = owner.splitn(2, ':').map(|id| id.parse().ok()).collect();
+
+        options.user.owner = match ids.as_slice() {
+            [Some(uid), Some(gid)] => Some((*uid, *gid)),
+            [Some(uid)] => Some((*uid, *uid)),
+            _ => {
+                return Err(ConfigError::Argument(format!(
+                    "Invalid owner '{}', expected UID[:GID]",
+                    owner
+                )))
+            }
+        };
+    }
+
+    options.base = export_matches.value_of("base").map(String::from);
+
+    export::export(&mut archive_cfg, format, &options, &dst)
+}
+
+/// Import the files managed by another dotfile manager into a new configuration file, and
+/// optionally archive them.
+fn import(
+    import_matches: &ArgMatches,
+    report: &mut Report,
+    _text: bool,
+) -> Result<(), ConfigError> {
+    // the possible values are restricted by clap
+    let source = import::Source::from_name(import_matches.value_of("from").unwrap()).unwrap();
+    let dirs: Vec<&Path> = import_matches.values_of("dir").unwrap().map(Path::new).collect();
+
+    let path = config_file(import_matches)?;
+
+    if path.exists() && !import_matches.is_present("force") {
+        return Err(ConfigError::Argument(format!(
+            "Refusing to overwrite existing config file '{}' without --force",
+            path.display()
+        )));
+    }
+
+    let specifier = import::import(source, &dirs)?;
+    report.add_paths(
+        specifier
+            .archive_paths()?
+            .iter()
+            .map(|path| path.to_tar_path()),
+        Action::Imported,
+    );
+
+    let cfg = ConfigArchive::new(Some(specifier), None);
+
+    cfg.write_config(&path)?;
+
+    if let Some(title) = import_matches.value_of("archive") {
+        let mut title = String::from(title);
+
+        if !title.ends_with(".tar") {
+            title.push_str(".tar");
+        }
+
+        cfg.write_archive(Path::new(&title), &ArchiveOptions::default())?;
+    }
+
+    Ok(())
+}
+
+/// Refresh an archive from the local system and print the entries which differ.
+fn update(update_matches: &ArgMatches, report: &mut Report, text: bool) -> Result<(), ConfigError> {
+    let tar_path = Path::new(update_matches.value_of("archive").unwrap());
+    let options = ArchiveOptions {
+        strict: update_matches.is_present("strict"),
+    };
+    let updated = ConfigArchive::update_archive(tar_path, &options)?;
+    let diff = &updated.diff;
+
+    report.add_paths(&diff.changed, Action::Changed);
+    report.add_paths(&diff.added, Action::Added);
+    report.add_paths(&diff.vanished, Action::Vanished);
+    report_skipped(report, &updated.skipped);
+
+    if !text {
+        return Ok(());
+    }
+
+    print_skipped(&updated.skipped);
+
+    if diff.is_empty() {
+        println!("no changes");
+    }
+
+    for path in &diff.changed {
+        println!("changed  {}", path.display());
+    }
+
+    for path in &diff.added {
+        println!("added    {}", path.display());
+    }
+
+    for path in &diff.vanished {
+        println!("vanished {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Merge several archives into one, printing every entry replaced by a later archive.
+fn merge(merge_matches: &ArgMatches, report: &mut Report, text: bool) -> Result<(), ConfigError> {
+    let tar_paths: Vec<&str> = merge_matches.values_of("archive").unwrap().collect();
+    let mut overlay = Overlay::with_archives(&tar_paths)?;
+
+    let mut title = String::from(merge_matches.value_of("merged").unwrap());
+
+    if !title.ends_with(".tar") {
+        title.push_str(".tar");
+    }
+
+    report.overridden = overlay.write_merged(Path::new(&title))?;
+
+    if text {
+        print_overridden(report);
+    }
+
+    Ok(())
+}
+
+/// Print every entry of an earlier archive replaced by a later archive.
+fn print_overridden(report: &Report) {
+    for overridden in &report.overridden {
+        println!(
+            "{} from {} overridden by {}",
+            overridden.path.display(),
+            overridden.layer,
+            overridden.by
+        );
+    }
 }
 
-fn main() -> Result<(), ConfigError> {
+fn main() {
     let matches = App::new("rconf")
         .about("backup and deploy configuration files.")
         .version(crate_version!())
+        .arg(Arg::with_name("output")
+            .long("output")
+            .global(true)
+            .value_name("FORMAT")
+            .possible_values(OUTPUT_FORMATS)
+            .default_value("text")
+            .help("the format of the report written to stdout, json reports every entry, package manager command, and error"))
         // create an archive according to specifications contained in a file
         .subcommand(SubCommand::with_name("archive")
             .about("create an archive as specified by the config file.")
@@ -112,22 +602,94 @@ fn main() -> Result<(), ConfigError> {
                 .help("the parent directory in which to store the resulting archive (defaults to the current working directory)"))
             .arg(Arg::with_name("title")
                  // .hidden(true)
-                .required(true)
+                .required_unless("to")
                 .value_name("TITLE")
                 .help("the name of the resulting archive, if the .tar extension is missig it will be added"))
+            .arg(Arg::with_name("to")
+                .short("o")
+                .value_name("FILE")
+                .conflicts_with_all(&["title", "destination"])
+                .help("the path to write the archive to as is, or - to write it to stdout"))
+            .arg(Arg::with_name("since")
+                .long("since")
+                .value_name("BASE")
+                .help("only archive the files which changed since the base archive"))
+            .arg(Arg::with_name("strict")
+                .long("strict")
+                .help("fail instead of skipping configured paths which do not exist, unless they are optional"))
                 .setting(AppSettings::ArgRequiredElseHelp))
+        // lint a config file before archiving it
+        .subcommand(SubCommand::with_name("check")
+            .about("check a config file for missing, misplaced, and duplicate paths and an unusable package manager")
+            .arg(Arg::with_name("config_file")
+                .short("f")
+                .long("file")
+                .value_name("FILE")
+                .help("the config file to check (defaults to the default config file)"))
+            .arg(Arg::with_name("upgrade")
+                .long("upgrade")
+                .takes_value(false)
+                .help("also require the package manager arguments needed to install with --upgrade")))
         // install system configurations and packages
         .subcommand(SubCommand::with_name("install")
             .about("attempt to install configurations from a given archive")
             .arg(Arg::with_name("archive")
                 .hidden(true)
                 .required(true)
+                .multiple(true)
                 .value_name("ARCHIVE")
-                .help("the path to the archive to be unpacked"))
+                .help("the archives to install in order of increasing precedence, such as a base archive followed by its incremental archives, or - to read one from stdin"))
             .arg(Arg::with_name("upgrade")
                 .long("upgrade")
                 .takes_value(false)
+                .conflicts_with("no_packages")
                 .help("if available upgrade the system using the package manger before installing"))
+            .arg(Arg::with_name("on_conflict")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .possible_values(ConflictPolicy::NAMES)
+                .help("what to do with existing files whose content differs from the archive, unless the config specifies a policy for the path (defaults to overwrite)"))
+            .arg(Arg::with_name("merge")
+                .long("merge")
+                .takes_value(false)
+                .help("merge the changes to existing files since they were last installed with the archive, conflicting changes are kept between conflict markers"))
+            .arg(Arg::with_name("plain")
+                .long("plain")
+                .takes_value(false)
+                .help("install archives without a manifest as plain tars, whose entries are installed like archived paths"))
+            .arg(Arg::with_name("root")
+                .long("root")
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("[NAME=]DIR")
+                .help("install the paths of a named root to the given directory instead of the one in the archive's config, or without a name install every file beneath the directory, such as a mounted system"))
+            .arg(Arg::with_name("home")
+                .long("home")
+                .value_name("DIR")
+                .help("install the files of the user's home and their config, data, state, cache, and bin directories beneath the given home directory"))
+            .arg(Arg::with_name("escalate")
+                .long("escalate")
+                .value_name("COMMAND")
+                .help("run the package manager and write the files outside of the home directory with the given command, such as sudo or doas, asking for privileges once after everything else is installed"))
+            .arg(Arg::with_name("user")
+                .long("user")
+                .value_name("NAME")
+                .conflicts_with_all(&["home", "all_users", "skel"])
+                .help("install the files of the user's home to the home directory of the given user, who is given ownership of them"))
+            .arg(Arg::with_name("all_users")
+                .long("all-users")
+                .takes_value(false)
+                .conflicts_with_all(&["home", "skel"])
+                .help("install the files of the user's home to every directory in /home, giving ownership of them to the directory's owner"))
+            .arg(Arg::with_name("skel")
+                .long("skel")
+                .takes_value(false)
+                .conflicts_with_all(&["home", "merge"])
+                .help("install the files of the user's home to /etc/skel, so they are copied to the home directory of new users"))
+            .arg(Arg::with_name("no_packages")
+                .long("no-packages")
+                .takes_value(false)
+                .help("only install the configuration files and not the packages"))
                 .setting(AppSettings::ArgRequiredElseHelp))
         // uninstall system configurations and packages
         .subcommand(SubCommand::with_name("remove")
@@ -138,22 +700,130 @@ fn main() -> Result<(), ConfigError> {
                 .value_name("ARCHIVE")
                 .help("the path to the archive to be unpacked"))
             .setting(AppSettings::ArgRequiredElseHelp))
+        // refresh an archive from the local system
+        .subcommand(SubCommand::with_name("update")
+            .about("refresh an archive with the current content of the paths in its embedded config")
+            .arg(Arg::with_name("archive")
+                .hidden(true)
+                .required(true)
+                .value_name("ARCHIVE")
+                .help("the path to the archive to be updated"))
+            .arg(Arg::with_name("strict")
+                .long("strict")
+                .help("fail instead of skipping configured paths which do not exist, unless they are optional"))
+            .setting(AppSettings::ArgRequiredElseHelp))
+        // combine several archives into one
+        .subcommand(SubCommand::with_name("merge")
+            .about("merge archives into one, the files and package manager of later archives take precedence")
+            .arg(Arg::with_name("archive")
+                .hidden(true)
+                .required(true)
+                .multiple(true)
+                .value_name("ARCHIVE")
+                .help("the archives to merge in order of increasing precedence"))
+            .arg(Arg::with_name("merged")
+                .short("o")
+                .required(true)
+                .value_name("TITLE")
+                .help("the name of the merged archive, if the .tar extension is missing it will be added"))
+            .setting(AppSettings::ArgRequiredElseHelp))
+        // translate an archive for use by other provisioning tools
+        .subcommand(SubCommand::with_name("export")
+            .about("export an archive into a format used by other provisioning tools")
+            .arg(Arg::with_name("archive")
+                .hidden(true)
+                .required(true)
+                .value_name("ARCHIVE")
+                .help("the path to the archive to be exported"))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .required(true)
+                .value_name("FORMAT")
+                .possible_values(export::Format::NAMES)
+                .help("the format to export the archive as"))
+            .arg(Arg::with_name("destination")
+                .short("d")
+                .long("dest")
+                .value_name("DIR")
+                .help("the directory in which to write the exported files (defaults to the current working directory)"))
+            .arg(Arg::with_name("user")
+                .long("user")
+                .value_name("NAME")
+                .help("the container user to deploy home and config paths for (defaults to root)"))
+            .arg(Arg::with_name("home")
+                .long("home")
+                .value_name("DIR")
+                .help("the home directory of the container user (defaults to /root or /home/NAME)"))
+            .arg(Arg::with_name("owner")
+                .long("owner")
+                .value_name("UID[:GID]")
+                .help("the numeric owner of the user's files in an oci-layer"))
+            .arg(Arg::with_name("base")
+                .long("base")
+                .value_name("IMAGE")
+                .help("the default base image of a dockerfile"))
+            .setting(AppSettings::ArgRequiredElseHelp))
+        // build a config from the files of another dotfile manager
+        .subcommand(SubCommand::with_name("import")
+            .about("create a config file from the files managed by another dotfile manager")
+            .arg(Arg::with_name("from")
+                .long("from")
+                .required(true)
+                .value_name("MANAGER")
+                .possible_values(import::Source::NAMES)
+                .help("the dotfile manager the directories belong to"))
+            .arg(Arg::with_name("dir")
+                .hidden(true)
+                .required(true)
+                .multiple(true)
+                .value_name("DIR")
+                .help("the stow packages, chezmoi source directory, or bare git repository to import"))
+            .arg(Arg::with_name("config_file")
+                .short("f")
+                .long("file")
+                .value_name("FILE")
+                .help("the config file to write (defaults to the default config file)"))
+            .arg(Arg::with_name("force")
+                .long("force")
+                .takes_value(false)
+                .help("overwrite the config file if it already exists"))
+            .arg(Arg::with_name("archive")
+                .short("a")
+                .long("archive")
+                .value_name("TITLE")
+                .help("also create an archive of the imported files, if the .tar extension is missing it will be added"))
+            .setting(AppSettings::ArgRequiredElseHelp))
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .get_matches();
 
-    let result = match matches.subcommand_name() {
-        Some("install") => install(matches.subcommand_matches("install").unwrap()),
-        Some("archive") => archive(matches.subcommand_matches("archive").unwrap()),
-        Some("remove") => remove(matches.subcommand_matches("remove").unwrap()),
+    let (name, sub_matches) = matches.subcommand();
+    let sub_matches = sub_matches.unwrap(); // missing SubCommand handled ^^^ by get_matches
+
+    let json = sub_matches.value_of("output") == Some("json");
+    let mut report = Report::new(name);
+
+    let result = match name {
+        "install" => install(sub_matches, &mut report, !json),
+        "archive" => archive(sub_matches, &mut report, !json),
+        "check" => check(sub_matches, &mut report, !json),
+        "remove" => remove(sub_matches, &mut report, !json),
+        "update" => update(sub_matches, &mut report, !json),
+        "merge" => merge(sub_matches, &mut report, !json),
+        "export" => export(sub_matches, &mut report, !json),
+        "import" => import(sub_matches, &mut report, !json),
         _ => Ok(()), // unrecognized SubCommand handled ^^^ by get_matches
     };
 
-    // nicely print any errors to the console
-    match result {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            eprintln!("{}", err.to_string());
-            std::process::exit(1);
-        }
+    report.finish(result);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else if let Some(err) = &report.error {
+        // nicely print any errors to the console
+        eprintln!("{}", err);
+    }
+
+    if !report.success {
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,161 @@
+//! Machine readable reports of the work done by a command, see `--output json`.
+use super::check::Problem;
+use super::configs::error::{ConfigError, Result};
+use super::configs::manager::Invocation;
+use super::configs::overlay::Overridden;
+use super::configs::path::{ArchivePath, ArchivedEntry, PathKind};
+use std::path::{Path, PathBuf};
+
+/// What was done to an entry.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Archived,
+    Installed,
+    Removed,
+    Skipped,
+    Merged,
+    Changed,
+    Added,
+    Vanished,
+    Imported,
+
+    /// A configured path which was not archived because it does not exist.
+    Missing,
+}
+
+/// An archive entry processed by a command.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EntryReport {
+    /// The relative path of the entry inside the archive.
+    pub path: PathBuf,
+    pub kind: PathKind,
+
+    /// The location of the entry on the local system, if it could be determined.
+    pub destination: Option<PathBuf>,
+    pub action: Action,
+
+    /// The name of the layer which supplied the entry when installing several archives.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+
+    /// Where the existing local file was moved before the entry was installed, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+
+    /// The lines of a merged file which start a conflict between the local and archived changes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<usize>,
+}
+
+impl EntryReport {
+    /// Report an action on the entry with the given archive path, or none for the paths which are
+    /// not configuration files such as the manifest.
+    pub fn new(tar_path: &Path, action: Action) -> Option<EntryReport> {
+        let path = ArchivePath::from_tar_path(tar_path)?;
+
+        let destination = path.to_local_path().ok();
+
+        Some(EntryReport {
+            path: tar_path.to_path_buf(),
+            kind: path.kind,
+            destination,
+            action,
+            layer: None,
+            backup: None,
+            conflicts: vec![],
+        })
+    }
+}
+
+/// The outcome of a single command.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// The name of the subcommand which was run.
+    pub command: String,
+    pub success: bool,
+    pub entries: Vec<EntryReport>,
+
+    /// The package manager commands which were run successfully.
+    pub invocations: Vec<Invocation>,
+    pub overridden: Vec<Overridden>,
+
+    /// The problems found by checking a configuration file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<Problem>,
+
+    /// The error which stopped the command, a failed package manager command is reported here.
+    pub error: Option<ConfigError>,
+}
+
+impl Report {
+    pub fn new(command: &str) -> Report {
+        Report {
+            command: command.to_string(),
+            success: true,
+            entries: vec![],
+            invocations: vec![],
+            overridden: vec![],
+            problems: vec![],
+            error: None,
+        }
+    }
+
+    /// Add an action on every configuration file among the archived entries.
+    pub fn add_entries(&mut self, entries: &[ArchivedEntry], action: Action) {
+        self.add_paths(entries.iter().map(|entry| &entry.tar_path), action);
+    }
+
+    /// Add an action on every configuration file among the archive paths.
+    pub fn add_paths<I, P>(&mut self, paths: I, action: Action)
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.entries.extend(
+            paths
+                .into_iter()
+                .filter_map(|path| EntryReport::new(path.as_ref(), action)),
+        );
+    }
+
+    /// Record the result of the command, keeping any error.
+    pub fn finish(&mut self, result: Result<()>) {
+        if let Err(err) = result {
+            self.success = false;
+            self.error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Report};
+    use crate::configs::error::ConfigError;
+    use std::path::Path;
+
+    #[test]
+    fn test_report_json() {
+        let mut report = Report::new("update");
+        report.add_paths(
+            vec![Path::new(".rconf"), Path::new("absolute/etc/hosts")],
+            Action::Changed,
+        );
+        report.finish(Err(ConfigError::Exit {
+            command: "pacman".to_string(),
+            args: vec!["-S".to_string()],
+            code: Some(1),
+            stderr: "error: target not found".to_string(),
+        }));
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(false, json["success"]);
+        assert_eq!(1, json["entries"].as_array().unwrap().len());
+        assert_eq!("absolute", json["entries"][0]["kind"]);
+        assert_eq!("/etc/hosts", json["entries"][0]["destination"]);
+        assert_eq!("changed", json["entries"][0]["action"]);
+        assert_eq!("exit_status", json["error"]["code"]);
+        assert_eq!(1, json["error"]["exit_code"]);
+    }
+}
@@ -1,45 +1,336 @@
+use super::configs::error::Result;
+use super::configs::path::{ArchivePath, ArchivedEntry, PathKind, Roots};
 use super::configs::ConfigArchive;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Preamble shared by the generated scripts handling argument parsing, dry runs, and locating the
+/// unpacked archive and target directories.
+const PREAMBLE: &str = r#"set -e
+
+dry_run=0
+for arg in "$@"; do
+    case "$arg" in
+        -n|--dry-run) dry_run=1 ;;
+        -h|--help)
+            echo "usage: $0 [--dry-run]"
+            exit 0 ;;
+        *)
+            echo "unrecognized argument: $arg" >&2
+            exit 1 ;;
+    esac
+done
+
+# print the command instead of running it when performing a dry run
+run() {
+    if [ "$dry_run" -eq 1 ]; then
+        echo "$@"
+    else
+        "$@"
+    fi
+}
+
+cd "$(dirname "$0")"
+
+home="${HOME:?HOME is not set}"
+config="${XDG_CONFIG_HOME:-$home/.config}"
+data="${XDG_DATA_HOME:-$home/.local/share}"
+state="${XDG_STATE_HOME:-$home/.local/state}"
+cache="${XDG_CACHE_HOME:-$home/.cache}"
+bin="${XDG_BIN_HOME:-$home/.local/bin}"
+"#;
+
+/// Quote a string for safe use as a single word in a POSIX shell.
+pub(crate) fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Build the name of the script variable holding a root's directory. Characters which may not be
+/// used in a variable name, and underscores, are escaped by their hex code so that every root has
+/// its own variable (ex `my-root` => `root_my_2droot`).
+fn root_variable(name: &str) -> String {
+    let mut variable = String::from("root_");
+
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            variable.push(byte as char);
+        } else {
+            variable.push_str(&format!("_{:02x}", byte));
+        }
+    }
+
+    variable
+}
+
+/// Build the assignments of the variables holding the directories of the roots, which default to
+/// the directories on the system the script was built on and can be overridden by setting
+/// `RCONF_<variable>` (ex `RCONF_root_workspace=/srv/work ./install.sh`).
+fn root_variables(roots: &Roots) -> String {
+    let mut variables = String::new();
+
+    for (name, dir) in &roots.dirs {
+        variables.push_str(&format!(
+            "{variable}={dir}\n{variable}=\"${{RCONF_{variable}:-${variable}}}\"\n",
+            variable = root_variable(name),
+            dir = quote(&dir.to_string_lossy()),
+        ));
+    }
+
+    variables
+}
+
+/// Build the shell expression for the location of an archived entry on the local system. The
+/// paths of a root are installed beneath the root's variable, see
+/// [root_variables](fn.root_variables.html).
+///
+/// # Errors
+/// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned if the path's root is
+/// not given.
+fn destination(path: &ArchivePath, roots: &Roots) -> Result<String> {
+    let relative = path.path.to_string_lossy();
+
+    Ok(match &path.kind {
+        PathKind::ABSOLUTE => quote(&format!("/{}", relative)),
+        PathKind::HOME => format!("\"$home\"/{}", quote(&relative)),
+        PathKind::CONFIG => format!("\"$config\"/{}", quote(&relative)),
+        PathKind::DATA => format!("\"$data\"/{}", quote(&relative)),
+        PathKind::STATE => format!("\"$state\"/{}", quote(&relative)),
+        PathKind::CACHE => format!("\"$cache\"/{}", quote(&relative)),
+        PathKind::BIN => format!("\"$bin\"/{}", quote(&relative)),
+        PathKind::ROOT(name) => {
+            // the root must be given for its variable to be assigned
+            roots.dir(name)?;
+            format!("\"${}\"/{}", root_variable(name), quote(&relative))
+        }
+    })
+}
+
+/// Build the command line for the package manager, or none if there is nothing to run.
+fn manager_command(cfg: &ConfigArchive, args: &[String]) -> Option<String> {
+    let manager = cfg.manager.as_ref()?;
+
+    if manager.packages.is_empty() {
+        return None;
+    }
+
+    let words: Vec<String> = std::iter::once(&manager.name)
+        .chain(args)
+        .chain(&manager.packages)
+        .map(|word| quote(word))
+        .collect();
+
+    Some(format!("run {}\n", words.join(" ")))
+}
 
 /// Generate the content for an installer script to operate on an unpacked rconf tar.
-pub fn build_script(cfg: &ConfigArchive) -> String {
-    let mut script = String::from("#!/usr/bin/env bash\n");
-
-    if let Some(specifier) = &cfg.paths {
-        if specifier.home.is_some() {
-            script.push_str(
-                "if [ -d home ];then
-    find home -maxdepth 1 -exec cp --verbose --recursive --target-directory $HOME '{}' +
-fi\n",
-            )
-        }
-
-        if specifier.config.is_some() {
-            script.push_str(
-                "if [ -d config ];then
-    find config -maxdepth 1 -exec cp --verbose --recursive --target-directory $HOME/.config '{}' +
-fi\n",
-            )
-        }
-
-        if specifier.absolute.is_some() {
-            script.push_str("abs=($(find . -maxdepth 1 -not \\( -regex './install.sh' -or -regex '.' -or -regex './home.*' -or -regex './.rconf' -or -regex './config.*' \\)))
-for file in \"${abs[@]}\"; do
-    cp --verbose --recursive $file ${file:1}
-done")
-        }
-    }
-
-    if let Some(manager) = &cfg.manager {
-        script.push_str(
-            format!(
-                "{} {} {}",
-                manager.name,
-                manager.install_args.join(" "),
-                manager.packages.join(" ")
-            )
-            .as_str(),
+///
+/// The packages are installed before every archived entry is copied explicitly to its
+/// destination, so the script depends on nothing but a POSIX shell and the standard utilities.
+///
+/// # Errors
+/// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned if an entry's root is
+/// not given.
+pub fn build_install_script(
+    cfg: &ConfigArchive,
+    roots: &Roots,
+    entries: &[ArchivedEntry],
+) -> Result<String> {
+    let mut script =
+        String::from("#!/bin/sh\n# install the configurations and packages in this archive\n");
+    script.push_str(PREAMBLE);
+    script.push_str(&root_variables(roots));
+    script.push('\n');
+
+    // the packages are installed first, as by rconf, so the files they ship are replaced by
+    // the archived files
+    if let Some(command) = cfg
+        .manager
+        .as_ref()
+        .and_then(|manager| manager_command(cfg, &manager.install_args))
+    {
+        script.push_str(&command);
+    }
+
+    // remove the entries which no longer exist since the base of an incremental archive
+    if let Some(incremental) = &cfg.incremental {
+        for removed in &incremental.removed {
+            if let Some(path) = ArchivePath::from_tar_path(Path::new(removed)) {
+                script.push_str(&format!("run rm -rf {}\n", destination(&path, roots)?));
+            }
+        }
+    }
+
+    // track the created directories to avoid redundant mkdir calls
+    let mut created: HashSet<PathBuf> = HashSet::new();
+
+    for entry in entries {
+        let path = match ArchivePath::from_tar_path(&entry.tar_path) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        if entry.is_dir {
+            script.push_str(&format!("run mkdir -p {}\n", destination(&path, roots)?));
+            created.insert(entry.tar_path.clone());
+            continue;
+        }
+
+        // make sure the parent exists for files which were not archived as part of a directory
+        if let Some(parent) = entry.tar_path.parent() {
+            if !created.contains(parent) {
+                if let Some(parent_path) = ArchivePath::from_tar_path(parent) {
+                    script.push_str(&format!(
+                        "run mkdir -p {}\n",
+                        destination(&parent_path, roots)?
+                    ));
+                }
+                created.insert(parent.to_path_buf());
+            }
+        }
+
+        script.push_str(&format!(
+            "run cp {} {}\n",
+            quote(&entry.tar_path.to_string_lossy()),
+            destination(&path, roots)?
+        ));
+    }
+
+    Ok(script)
+}
+
+/// Generate the content for an uninstaller script reverting the changes made by the script from
+/// [build_install_script](fn.build_install_script.html).
+///
+/// Archived files are removed, while archived directories are only removed once they are empty.
+///
+/// # Errors
+/// A [ConfigError](../configs/error/enum.ConfigError.html) will be returned if an entry's root is
+/// not given.
+pub fn build_uninstall_script(
+    cfg: &ConfigArchive,
+    roots: &Roots,
+    entries: &[ArchivedEntry],
+) -> Result<String> {
+    let mut script =
+        String::from("#!/bin/sh\n# uninstall the configurations and packages in this archive\n");
+    script.push_str(PREAMBLE);
+    script.push_str(&root_variables(roots));
+    script.push('\n');
+
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        if let Some(path) = ArchivePath::from_tar_path(&entry.tar_path) {
+            script.push_str(&format!("run rm -f {}\n", destination(&path, roots)?));
+        }
+    }
+
+    // remove directories deepest first so that nested directories are emptied before parents
+    for entry in entries.iter().rev().filter(|entry| entry.is_dir) {
+        if let Some(path) = ArchivePath::from_tar_path(&entry.tar_path) {
+            script.push_str(&format!(
+                "run rmdir {} 2>/dev/null || true\n",
+                destination(&path, roots)?
+            ));
+        }
+    }
+
+    if let Some(command) = cfg.manager.as_ref().and_then(|manager| {
+        manager
+            .un_install_args
+            .as_ref()
+            .and_then(|args| manager_command(cfg, args))
+    }) {
+        script.push_str(&command);
+    }
+
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_install_script, build_uninstall_script, quote};
+    use crate::configs::path::{ArchivedEntry, Roots};
+    use crate::configs::ConfigArchive;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, is_dir: bool) -> ArchivedEntry {
+        ArchivedEntry {
+            tar_path: PathBuf::from(path),
+            is_dir,
+        }
+    }
+
+    fn config() -> ConfigArchive {
+        toml::from_str(
+            "manager.name = 'pacman'
+manager.install_args = ['-S']
+manager.un_install_args = ['-R']
+manager.packages = ['fish']",
         )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("'a b'", quote("a b"));
+        assert_eq!("'it'\\''s'", quote("it's"));
+    }
+
+    #[test]
+    fn test_install_script() {
+        let entries = vec![
+            entry("absolute/etc/gitconfig", false),
+            entry("home/my dir", true),
+            entry("home/my dir/file", false),
+            entry("config/nvim/init.vim", false),
+        ];
+
+        let script = build_install_script(&config(), &Roots::default(), &entries).unwrap();
+
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("set -e\n"));
+        assert!(script
+            .contains("run mkdir -p '/etc'\nrun cp 'absolute/etc/gitconfig' '/etc/gitconfig'\n"));
+        assert!(script.contains("run mkdir -p \"$home\"/'my dir'\n"));
+        assert!(script.contains("run cp 'home/my dir/file' \"$home\"/'my dir/file'\n"));
+        assert!(script.contains("run mkdir -p \"$config\"/'nvim'\n"));
+        assert!(script.contains("\nrun 'pacman' '-S' 'fish'\nrun mkdir -p '/etc'\n"));
+        assert!(script.ends_with("run cp 'config/nvim/init.vim' \"$config\"/'nvim/init.vim'\n"));
     }
 
-    script
+    #[test]
+    fn test_root_script() {
+        let roots = Roots {
+            dirs: vec![
+                ("workspace".to_string(), PathBuf::from("/srv/ws")),
+                ("my-root".to_string(), PathBuf::from("/opt/it's")),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let entries = vec![entry("roots/workspace/.editorconfig", false)];
+
+        let script = build_install_script(&config(), &roots, &entries).unwrap();
+
+        // the root directories can be overridden when running the script
+        assert!(script.contains(
+            "root_workspace='/srv/ws'\nroot_workspace=\"${RCONF_root_workspace:-$root_workspace}\"\n"
+        ));
+        assert!(script.contains("root_my_2droot='/opt/it'\\''s'\n"));
+        assert!(script.contains(
+            "run cp 'roots/workspace/.editorconfig' \"$root_workspace\"/'.editorconfig'\n"
+        ));
+        assert!(build_install_script(&config(), &Roots::default(), &entries).is_err());
+    }
+
+    #[test]
+    fn test_uninstall_script() {
+        let entries = vec![entry("home/dir", true), entry("home/dir/file", false)];
+
+        let script = build_uninstall_script(&config(), &Roots::default(), &entries).unwrap();
+
+        assert!(script.contains(
+            "run rm -f \"$home\"/'dir/file'\nrun rmdir \"$home\"/'dir' 2>/dev/null || true\nrun 'pacman' '-R' 'fish'\n"
+        ));
+    }
 }